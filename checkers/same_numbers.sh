@@ -0,0 +1,17 @@
+#!/bin/sh
+# Accepts the output if the list after its header contains the same integers,
+# in the same order, as the expected output's, whatever separates them. The
+# header is everything up to the first colon, so the numbers it repeats from the
+# input are not compared; output without a colon is compared as a whole.
+# Usage: same_numbers.sh input expected actual
+numbers() {
+    tr '\n' ' ' < "$1" | sed 's/^[^:]*://' | grep -oE -- '-?[0-9]+' | tr '\n' ' '
+}
+expected=$(numbers "$2")
+actual=$(numbers "$3")
+if [ "$expected" = "$actual" ]; then
+    exit 0
+fi
+echo "expected the numbers: $expected"
+echo "got: $actual"
+exit 1
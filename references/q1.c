@@ -0,0 +1,34 @@
+/* Reference solution for q1: averages of the positive and negative values among 15 inputs. */
+#include <stdio.h>
+
+#define COUNT 15
+
+int main(void) {
+    double value;
+    double positive_sum = 0, negative_sum = 0;
+    int positives = 0, negatives = 0, read = 0;
+    while (scanf("%lf", &value) == 1) {
+        if (++read > COUNT) {
+            printf("Error: Too many inputs provided\n");
+            return 0;
+        }
+        if (value > 0) {
+            positive_sum += value;
+            positives++;
+        } else if (value < 0) {
+            negative_sum += value;
+            negatives++;
+        }
+    }
+    if (read < COUNT) {
+        printf("Invalid input\n");
+        return 0;
+    }
+    if (positives > 0) {
+        printf("Average of positive values: %.2f\n", positive_sum / positives);
+    }
+    if (negatives > 0) {
+        printf("Average of negative values: %.2f\n", negative_sum / negatives);
+    }
+    return 0;
+}
@@ -0,0 +1,21 @@
+/* Reference solution for q16: prints every identifier in the input, one per line. */
+#include <ctype.h>
+#include <stdio.h>
+
+int main(void) {
+    int c;
+    int in_identifier = 0;
+    while ((c = getchar()) != EOF) {
+        if (isalpha(c) || c == '_' || (in_identifier && isdigit(c))) {
+            putchar(c);
+            in_identifier = 1;
+        } else if (in_identifier) {
+            putchar('\n');
+            in_identifier = 0;
+        }
+    }
+    if (in_identifier) {
+        putchar('\n');
+    }
+    return 0;
+}
@@ -0,0 +1,394 @@
+// The versioned JSON API under /api/v1, for scripts and editor plugins.
+//
+// Every request carries HTTP Basic credentials, checked by the `ApiUser` guard.
+// Responses show what the HTML pages would: students only find their own
+// submissions and see hidden tests in aggregate, while TAs and instructors see
+// everything. Errors are JSON objects with an `error` message.
+
+use rocket::form::Form;
+use rocket::http::{Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, status, Responder};
+use rocket::serde::json::Json;
+use rocket::{Catcher, Route, State};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::auth::{ApiUser, Role};
+use crate::grading::{FileDiff, TestResult, Verdict};
+use crate::interaction::Transcript;
+use crate::profile::Profiles;
+use crate::questions::{self, Question, TestCase, Visibility};
+use crate::reference::References;
+use crate::sandbox::Sandbox;
+use crate::scoring::Score;
+use crate::store::{Feedback, Store};
+use crate::{grade, read_upload, Attempt, GradeError, GradedSubmission, Upload, SEED_FORBIDDEN};
+
+/// Where the API is mounted.
+pub const BASE: &str = "/api/v1";
+
+pub fn routes() -> Vec<Route> {
+    routes![question_list, question, submit_json, submit_upload, submission]
+}
+
+pub fn catchers() -> Vec<Catcher> {
+    catchers![error]
+}
+
+/// A failed request, answered with `{"error": message}`.
+#[derive(Debug)]
+pub struct ApiError {
+    status: Status,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: Status, message: impl Into<String>) -> Self {
+        ApiError { status, message: message.into() }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = status::Custom(self.status, Json(ErrorBody { error: self.message })).respond_to(request)?;
+        if self.status == Status::Unauthorized {
+            response.set_header(Header::new("WWW-Authenticate", "Basic realm=\"autograder\""));
+        }
+        Ok(response)
+    }
+}
+
+/// Answers every error under /api/v1, including missing credentials and unknown routes.
+#[catch(default)]
+fn error(status: Status, _request: &Request) -> ApiError {
+    if status == Status::Unauthorized {
+        ApiError::new(
+            status,
+            "Send your student ID and login token, or an admin username and password, as HTTP Basic credentials",
+        )
+    } else if status == Status::TooManyRequests {
+        ApiError::new(status, "Too many wrong credentials; try again in a minute")
+    } else {
+        ApiError::new(status, status.reason().unwrap_or("Error"))
+    }
+}
+
+/// A question and what students may know about its tests.
+#[derive(Serialize)]
+struct QuestionInfo {
+    name: String,
+    /// What the grade is scaled to, if not the sum of the tests' points.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_points: Option<f64>,
+    /// Whether random tests are generated for each submission, on top of `tests`.
+    random_tests: bool,
+    /// The tests a student may see; TAs and instructors also get hidden ones.
+    tests: Vec<TestInfo>,
+    /// How many tests are hidden, listed or not.
+    hidden_tests: usize,
+}
+
+/// A test's public description, without its inputs or expected outputs.
+#[derive(Serialize)]
+struct TestInfo {
+    description: String,
+    visibility: Visibility,
+    points: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    time_limit_ms: u64,
+    interactive: bool,
+}
+
+impl QuestionInfo {
+    fn new(name: &str, question: &Question, staff: bool) -> Self {
+        let tests = question
+            .tests
+            .iter()
+            .filter(|case| staff || case.visibility != Visibility::Hidden)
+            .map(|case| TestInfo {
+                description: case.description.clone(),
+                visibility: case.visibility,
+                points: case.points(),
+                group: case.group.clone(),
+                time_limit_ms: question.time_limit_ms(case),
+                interactive: !case.interaction.is_empty(),
+            })
+            .collect();
+        QuestionInfo {
+            name: name.to_string(),
+            max_points: question.settings.max_points,
+            random_tests: question.settings.generator.is_some(),
+            tests,
+            hidden_tests: question.tests.iter().filter(|case| case.visibility == Visibility::Hidden).count(),
+        }
+    }
+}
+
+fn is_staff(user: &ApiUser) -> bool {
+    user.role >= Role::Ta
+}
+
+/// GET /api/v1/questions
+/// Every question, in order of name.
+#[get("/questions")]
+fn question_list(user: ApiUser) -> Result<Json<Vec<QuestionInfo>>, ApiError> {
+    let map = questions::load().map_err(|e| ApiError::new(Status::InternalServerError, e))?;
+    let mut names: Vec<&String> = map.keys().collect();
+    names.sort();
+    Ok(Json(names.into_iter().map(|name| QuestionInfo::new(name, &map[name], is_staff(&user))).collect()))
+}
+
+/// GET /api/v1/questions/<name>
+#[get("/questions/<name>")]
+fn question(name: &str, user: ApiUser) -> Result<Json<QuestionInfo>, ApiError> {
+    let map = questions::load().map_err(|e| ApiError::new(Status::InternalServerError, e))?;
+    match map.get(name) {
+        Some(question) => Ok(Json(QuestionInfo::new(name, question, is_staff(&user)))),
+        None => Err(ApiError::new(Status::NotFound, format!("No question named {}", name))),
+    }
+}
+
+/// Code submitted as JSON.
+#[derive(Deserialize)]
+struct NewSubmission {
+    question: String,
+    /// The C source file's contents.
+    source: String,
+    /// Seed for the question's random inputs; instructors only.
+    #[serde(default)]
+    seed: Option<u64>,
+}
+
+/// Where to find a new submission's results.
+#[derive(Serialize)]
+struct Created {
+    id: String,
+    url: String,
+}
+
+/// POST /api/v1/submissions, with a JSON body.
+/// Grades the code and returns the ID of the recorded submission.
+#[post("/submissions", format = "json", data = "<body>")]
+async fn submit_json(
+    body: Json<NewSubmission>,
+    user: ApiUser,
+    jail: &State<Arc<dyn Sandbox>>,
+    profiles: &State<Arc<Profiles>>,
+    references: &State<Arc<References>>,
+    store: &State<Arc<Store>>,
+) -> Result<status::Created<Json<Created>>, ApiError> {
+    let body = body.into_inner();
+    let attempt = Attempt { question: body.question, source: body.source.into_bytes(), seed: body.seed };
+    submit(attempt, &user, jail, profiles, references, store).await
+}
+
+/// POST /api/v1/submissions, with a multipart body like the upload form's:
+/// a `file`, a `question` and, for instructors, an optional `seed`.
+#[post("/submissions", format = "multipart", data = "<form>")]
+async fn submit_upload(
+    form: Form<Upload<'_>>,
+    user: ApiUser,
+    jail: &State<Arc<dyn Sandbox>>,
+    profiles: &State<Arc<Profiles>>,
+    references: &State<Arc<References>>,
+    store: &State<Arc<Store>>,
+) -> Result<status::Created<Json<Created>>, ApiError> {
+    if form.file.name().is_none() {
+        return Err(ApiError::new(Status::UnprocessableEntity, "No file uploaded"));
+    }
+    let source = read_upload(&form).await.map_err(grade_error)?;
+    let attempt = Attempt { question: form.question.clone(), source, seed: form.seed };
+    submit(attempt, &user, jail, profiles, references, store).await
+}
+
+async fn submit(
+    attempt: Attempt,
+    user: &ApiUser,
+    jail: &State<Arc<dyn Sandbox>>,
+    profiles: &State<Arc<Profiles>>,
+    references: &State<Arc<References>>,
+    store: &State<Arc<Store>>,
+) -> Result<status::Created<Json<Created>>, ApiError> {
+    if attempt.seed.is_some() && user.role != Role::Instructor {
+        return Err(ApiError::new(Status::Forbidden, SEED_FORBIDDEN));
+    }
+    let id = uuid::Uuid::new_v4().to_string();
+    match grade(attempt, &id, user.student.as_deref(), jail, profiles, references, store).await {
+        // A compile error is a result like any other, recorded for the student to fetch.
+        Ok(_) | Err(GradeError::Compile(_)) => {
+            let url = format!("{}/submissions/{}", BASE, id);
+            Ok(status::Created::new(url.clone()).body(Json(Created { id, url })))
+        }
+        Err(e) => Err(grade_error(e)),
+    }
+}
+
+fn grade_error(e: GradeError) -> ApiError {
+    match e {
+        GradeError::UnknownQuestion(question) => {
+            ApiError::new(Status::UnprocessableEntity, format!("No test cases found for question {}", question))
+        }
+        GradeError::Compile(e) => ApiError::new(Status::UnprocessableEntity, e.to_string()),
+        GradeError::Failed(message) => ApiError::new(Status::InternalServerError, message),
+    }
+}
+
+/// A recorded submission and its results.
+#[derive(Serialize)]
+struct SubmissionInfo {
+    id: String,
+    question: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    student: Option<String>,
+    /// UTC, as `YYYY-MM-DD HH:MM:SS`.
+    submitted_at: String,
+    compiled: bool,
+    /// gcc's errors if it did not compile, otherwise its warnings.
+    compiler_output: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    /// Absent if the submission did not compile.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<Score>,
+    /// Tests passed out of all tests run, hidden ones included.
+    passed: usize,
+    total: usize,
+    /// The tests the requester may see, in the order they ran.
+    tests: Vec<ResultInfo>,
+    /// Hidden tests left out of `tests`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hidden: Option<HiddenTotals>,
+    feedback: Vec<FeedbackInfo>,
+}
+
+#[derive(Serialize)]
+struct HiddenTotals {
+    passed: usize,
+    total: usize,
+}
+
+/// One test's result. Name-only tests have just a description and verdict.
+#[derive(Serialize)]
+struct ResultInfo {
+    description: String,
+    visibility: Visibility,
+    verdict: Verdict,
+    points: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    /// Partial credit between 0 and 1 awarded by the test's checker, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checker_score: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stderr: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<UsageInfo>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    file_diffs: Vec<FileDiff>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transcript: Option<Transcript>,
+}
+
+#[derive(Serialize)]
+struct UsageInfo {
+    cpu_ms: u128,
+    wall_ms: u128,
+    memory_bytes: u64,
+}
+
+#[derive(Serialize)]
+struct FeedbackInfo {
+    author: String,
+    body: String,
+    created_at: String,
+}
+
+impl From<Feedback> for FeedbackInfo {
+    fn from(feedback: Feedback) -> Self {
+        FeedbackInfo { author: feedback.author, body: feedback.body, created_at: feedback.created_at }
+    }
+}
+
+impl ResultInfo {
+    fn new(case: &TestCase, result: &TestResult, full: bool) -> Self {
+        ResultInfo {
+            description: result.description.clone(),
+            visibility: case.visibility,
+            verdict: result.verdict.clone(),
+            points: case.points(),
+            group: case.group.clone(),
+            checker_score: result.score.filter(|_| full),
+            details: Some(result.details.clone()).filter(|_| full),
+            stderr: Some(result.stderr.clone()).filter(|_| full),
+            usage: result.usage.filter(|_| full).map(|usage| UsageInfo {
+                cpu_ms: usage.cpu_time.as_millis(),
+                wall_ms: usage.wall_time.as_millis(),
+                memory_bytes: usage.memory,
+            }),
+            file_diffs: if full { result.file_diffs.clone() } else { Vec::new() },
+            transcript: result.transcript.clone().filter(|_| full),
+        }
+    }
+}
+
+/// GET /api/v1/submissions/<id>
+/// The submission's results, if the requester made it or is a TA or instructor.
+#[get("/submissions/<id>")]
+fn submission(id: &str, user: ApiUser, store: &State<Arc<Store>>) -> Result<Json<SubmissionInfo>, ApiError> {
+    let not_found = || ApiError::new(Status::NotFound, format!("No submission {}", id));
+    let stored = store
+        .load(id)
+        .map_err(|e| ApiError::new(Status::InternalServerError, format!("Error reading submission: {}", e)))?
+        .ok_or_else(not_found)?;
+    let staff = is_staff(&user);
+    // As on the HTML pages, other students' submissions are not found rather than forbidden.
+    if !staff && stored.summary.student != user.student {
+        return Err(not_found());
+    }
+    let feedback = store.feedback(id).unwrap_or_else(|e| {
+        eprintln!("Error reading feedback on {}: {}", id, e);
+        Vec::new()
+    });
+
+    let (question, submitted_at, student) =
+        (stored.summary.question.clone(), stored.summary.submitted_at.clone(), stored.summary.student.clone());
+    let (compiled, compiler_output) = (stored.summary.compiled, stored.compiler_output.clone());
+    let submission = GradedSubmission::from(stored);
+    let passed = submission.results.iter().filter(|result| result.verdict.passed()).count();
+    let mut hidden = HiddenTotals { passed: 0, total: 0 };
+    let mut tests = Vec::new();
+    for (case, result) in submission.tests.iter().zip(&submission.results) {
+        match case.visibility {
+            Visibility::Hidden if !staff => {
+                hidden.total += 1;
+                hidden.passed += usize::from(result.verdict.passed());
+            }
+            Visibility::NameOnly if !staff => tests.push(ResultInfo::new(case, result, false)),
+            _ => tests.push(ResultInfo::new(case, result, true)),
+        }
+    }
+    Ok(Json(SubmissionInfo {
+        id: submission.id,
+        question,
+        student,
+        submitted_at,
+        compiled,
+        compiler_output,
+        seed: submission.seed,
+        score: Some(submission.score).filter(|_| compiled),
+        passed,
+        total: submission.results.len(),
+        tests,
+        hidden: Some(hidden).filter(|hidden| hidden.total > 0),
+        feedback: feedback.into_iter().map(FeedbackInfo::from).collect(),
+    }))
+}
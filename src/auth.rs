@@ -0,0 +1,367 @@
+// Who is making a request: hashed credentials and login sessions.
+//
+// Students log in with their roster ID and the token generated when they were
+// imported; admins log in with a username and password from the configuration.
+// A session is a private cookie, encrypted with Rocket's secret key, so its
+// contents cannot be forged or altered by the browser.
+//
+// What someone may do depends on their role. Everyone on the roster is a
+// student unless an instructor makes them a TA or an instructor, and admins
+// from the configuration are always instructors. Routes state what they need
+// with the `User`, `Staff` or `Instructor` guard.
+//
+// Scripts using the JSON API have no session: they send the same credentials
+// with every request, and are identified by the `ApiUser` guard instead. Since
+// hashing is slow on purpose, credentials that were verified once are
+// remembered, and clients that keep sending wrong ones are turned away.
+
+use argon2::password_hash::phc::PasswordHash;
+use argon2::password_hash::{PasswordHasher, PasswordVerifier};
+use argon2::Argon2;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use rocket::http::{Cookie, CookieJar, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::time::{Duration, OffsetDateTime};
+use rocket::State;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use crate::config::GraderConfig;
+use crate::csrf;
+use crate::store::Store;
+
+/// Private cookie holding the logged-in student's ID.
+const STUDENT_COOKIE: &str = "student";
+
+/// Private cookie holding the logged-in admin's username and when their
+/// session expires, as `username unix-time`.
+const ADMIN_COOKIE: &str = "admin";
+
+/// An argon2 hash of `secret`, as a PHC string with its own random salt.
+pub fn hash(secret: &str) -> Result<String, String> {
+    Argon2::default()
+        .hash_password(secret.as_bytes())
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("Cannot hash credentials: {}", e))
+}
+
+/// Whether `secret` matches `hash`, a PHC string made by [`hash`].
+pub fn verify(secret: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| Argon2::default().verify_password(secret.as_bytes(), &hash).is_ok())
+}
+
+/// Whether `a` and `b` are equal, compared in constant time so that the
+/// response time gives nothing away.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Checks that `hash` is a PHC string [`verify`] can read.
+pub fn validate_hash(hash: &str) -> Result<(), String> {
+    PasswordHash::new(hash).map(|_| ()).map_err(|e| format!("invalid password hash: {}", e))
+}
+
+/// A fresh login token to hand to a student.
+pub fn new_token() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// What someone on the roster may do, from least to most.
+#[derive(Serialize, Deserialize, FromFormField, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Submits and sees their own results.
+    #[default]
+    Student,
+    /// Also sees every submission and leaves feedback on it.
+    Ta,
+    /// Also edits questions and tests and manages the roster.
+    Instructor,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Student, Role::Ta, Role::Instructor];
+
+    /// The role's name in forms, as serialized.
+    pub fn value(&self) -> &'static str {
+        match self {
+            Role::Student => "student",
+            Role::Ta => "ta",
+            Role::Instructor => "instructor",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Student => write!(f, "Student"),
+            Role::Ta => write!(f, "TA"),
+            Role::Instructor => write!(f, "Instructor"),
+        }
+    }
+}
+
+/// Someone on the roster who is logged in, from the session cookie. Requests
+/// without a session, or whose person has since been removed from the roster,
+/// are forwarded with 401 Unauthorized.
+#[derive(Debug, Clone)]
+pub struct User {
+    pub id: String,
+    pub name: String,
+    pub role: Role,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for User {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        let Some(cookie) = request.cookies().get_private(STUDENT_COOKIE) else {
+            return Outcome::Forward(Status::Unauthorized);
+        };
+        let store = match request.guard::<&State<Arc<Store>>>().await {
+            Outcome::Success(store) => store,
+            _ => return Outcome::Error((Status::InternalServerError, ())),
+        };
+        match store.student(cookie.value()) {
+            Ok(Some(student)) => Outcome::Success(User { id: student.id, name: student.name, role: student.role }),
+            Ok(None) => Outcome::Forward(Status::Unauthorized),
+            Err(e) => {
+                eprintln!("Error reading student {}: {}", cookie.value(), e);
+                Outcome::Error((Status::InternalServerError, ()))
+            }
+        }
+    }
+}
+
+/// A TA or instructor: a configured admin, or someone on the roster with one of
+/// those roles. Students are forwarded with 403 Forbidden, and requests without
+/// a session with 401 Unauthorized.
+#[derive(Debug, Clone)]
+pub struct Staff {
+    /// The admin's username or the person's name, shown as the author of their feedback.
+    pub name: String,
+    pub role: Role,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Staff {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        if let Outcome::Success(admin) = request.guard::<AdminUser>().await {
+            return Outcome::Success(Staff { name: admin.username, role: Role::Instructor });
+        }
+        match request.guard::<User>().await {
+            Outcome::Success(user) if user.role >= Role::Ta => Outcome::Success(Staff { name: user.name, role: user.role }),
+            Outcome::Success(_) => Outcome::Forward(Status::Forbidden),
+            Outcome::Forward(status) => Outcome::Forward(status),
+            Outcome::Error(e) => Outcome::Error(e),
+        }
+    }
+}
+
+/// An instructor: a configured admin, or someone on the roster with that role.
+/// Everyone else is forwarded as by [`Staff`], TAs with 403 Forbidden.
+#[derive(Debug, Clone)]
+pub struct Instructor;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Instructor {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        match request.guard::<Staff>().await {
+            Outcome::Success(staff) if staff.role == Role::Instructor => Outcome::Success(Instructor),
+            Outcome::Success(_) => Outcome::Forward(Status::Forbidden),
+            Outcome::Forward(status) => Outcome::Forward(status),
+            Outcome::Error(e) => Outcome::Error(e),
+        }
+    }
+}
+
+/// Starts a session for the student with ID `id`, lasting until the browser is closed.
+pub fn log_in_student(cookies: &CookieJar<'_>, id: &str) {
+    // Without an explicit session expiry, Rocket would keep the cookie for a week.
+    cookies.add_private(Cookie::build((STUDENT_COOKIE, id.to_string())).expires(None));
+    csrf::reset(cookies);
+}
+
+pub fn log_out_student(cookies: &CookieJar<'_>) {
+    cookies.remove_private(STUDENT_COOKIE);
+    csrf::reset(cookies);
+}
+
+/// A configured admin, logged in with an unexpired session cookie. Requests
+/// without one, or whose admin is no longer configured, are forwarded with 401
+/// Unauthorized. Routes use [`Instructor`], which admins always are.
+#[derive(Debug, Clone)]
+pub struct AdminUser {
+    pub username: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminUser {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        let Some(cookie) = request.cookies().get_private(ADMIN_COOKIE) else {
+            return Outcome::Forward(Status::Unauthorized);
+        };
+        let config = match request.guard::<&State<GraderConfig>>().await {
+            Outcome::Success(config) => config,
+            _ => return Outcome::Error((Status::InternalServerError, ())),
+        };
+        // The browser may keep the cookie past its expiry, so the session checks its own.
+        let session = cookie
+            .value()
+            .rsplit_once(' ')
+            .and_then(|(username, expires)| Some((username, expires.parse::<i64>().ok()?)));
+        match session {
+            Some((username, expires))
+                if expires > OffsetDateTime::now_utc().unix_timestamp() && config.admins.contains_key(username) =>
+            {
+                Outcome::Success(AdminUser { username: username.to_string() })
+            }
+            _ => Outcome::Forward(Status::Unauthorized),
+        }
+    }
+}
+
+/// Checks `password` against the configured admin `username`, hashing on a
+/// blocking thread rather than an async worker.
+pub async fn check_admin(config: &GraderConfig, username: &str, password: &str) -> bool {
+    let Some(hash) = config.admins.get(username).cloned() else {
+        return false;
+    };
+    let password = password.to_string();
+    rocket::tokio::task::spawn_blocking(move || verify(&password, &hash)).await.unwrap_or(false)
+}
+
+/// Starts a session for admin `username`, lasting the configured number of minutes.
+pub fn log_in_admin(cookies: &CookieJar<'_>, config: &GraderConfig, username: &str) {
+    let expires = OffsetDateTime::now_utc() + Duration::minutes(config.admin_session_minutes as i64);
+    let value = format!("{} {}", username, expires.unix_timestamp());
+    cookies.add_private(Cookie::build((ADMIN_COOKIE, value)).expires(expires));
+    csrf::reset(cookies);
+}
+
+pub fn log_out_admin(cookies: &CookieJar<'_>) {
+    cookies.remove_private(ADMIN_COOKIE);
+    csrf::reset(cookies);
+}
+
+/// Someone using the JSON API, identified on every request by HTTP Basic
+/// credentials: a student's roster ID and login token, or a configured admin's
+/// username and password. Session cookies are ignored, so another site cannot
+/// make a logged-in browser call the API. Requests without valid credentials
+/// are forwarded with 401 Unauthorized.
+#[derive(Debug, Clone)]
+pub struct ApiUser {
+    /// The roster ID submissions are recorded under; none for admins.
+    pub student: Option<String>,
+    pub role: Role,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiUser {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        let credentials = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Basic "))
+            .and_then(|encoded| BASE64_STANDARD.decode(encoded.trim()).ok())
+            .and_then(|decoded| String::from_utf8(decoded).ok());
+        let Some((id, secret)) = credentials.as_deref().and_then(|credentials| credentials.split_once(':')) else {
+            return Outcome::Forward(Status::Unauthorized);
+        };
+        let (config, store, logins) = match (
+            request.guard::<&State<GraderConfig>>().await,
+            request.guard::<&State<Arc<Store>>>().await,
+            request.guard::<&State<ApiLogins>>().await,
+        ) {
+            (Outcome::Success(config), Outcome::Success(store), Outcome::Success(logins)) => (config, store, logins),
+            _ => return Outcome::Error((Status::InternalServerError, ())),
+        };
+        let client = request.client_ip();
+        if logins.throttled(client) {
+            return Outcome::Error((Status::TooManyRequests, ()));
+        }
+
+        // Who `id` could be, with the hash their secret must match.
+        let mut candidates = Vec::new();
+        if let Some(hash) = config.admins.get(id) {
+            candidates.push((hash.clone(), ApiUser { student: None, role: Role::Instructor }));
+        }
+        match store.student(id) {
+            Ok(Some(student)) => {
+                candidates.push((student.token_hash, ApiUser { student: Some(student.id), role: student.role }))
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Error reading student {}: {}", id, e);
+                return Outcome::Error((Status::InternalServerError, ()));
+            }
+        }
+        for (hash, user) in candidates {
+            if logins.remembers(&hash, secret) {
+                return Outcome::Success(user);
+            }
+            let (checked_hash, checked_secret) = (hash.clone(), secret.to_string());
+            let verified = rocket::tokio::task::spawn_blocking(move || verify(&checked_secret, &checked_hash)).await;
+            if verified.unwrap_or(false) {
+                logins.remember(hash, secret);
+                return Outcome::Success(user);
+            }
+        }
+        logins.failed(client);
+        Outcome::Forward(Status::Unauthorized)
+    }
+}
+
+/// Failed API logins a client may make per [`API_FAILURE_WINDOW`] before it is
+/// answered with 429 Too Many Requests until the window ends.
+const MAX_API_FAILURES: u32 = 10;
+const API_FAILURE_WINDOW: Duration = Duration::minutes(1);
+
+/// What the API remembers about logins across requests.
+#[derive(Default)]
+pub struct ApiLogins {
+    /// Secrets that matched, by the hash they matched. Resetting a student's
+    /// token changes the hash, so the old token is no longer found.
+    verified: Mutex<HashMap<String, String>>,
+    /// Failed logins by client address, with when the client's window started.
+    failures: Mutex<HashMap<Option<IpAddr>, (u32, OffsetDateTime)>>,
+}
+
+impl ApiLogins {
+    fn remembers(&self, hash: &str, secret: &str) -> bool {
+        self.verified
+            .lock()
+            .unwrap()
+            .get(hash)
+            .is_some_and(|known| constant_time_eq(known.as_bytes(), secret.as_bytes()))
+    }
+
+    fn remember(&self, hash: String, secret: &str) {
+        self.verified.lock().unwrap().insert(hash, secret.to_string());
+    }
+
+    fn throttled(&self, client: Option<IpAddr>) -> bool {
+        let now = OffsetDateTime::now_utc();
+        let mut failures = self.failures.lock().unwrap();
+        failures.retain(|_, (_, started)| now - *started < API_FAILURE_WINDOW);
+        failures.get(&client).is_some_and(|(count, _)| *count >= MAX_API_FAILURES)
+    }
+
+    fn failed(&self, client: Option<IpAddr>) {
+        let mut failures = self.failures.lock().unwrap();
+        failures.entry(client).or_insert((0, OffsetDateTime::now_utc())).0 += 1;
+    }
+}
@@ -0,0 +1,67 @@
+// External checker programs for test cases that accept more than one correct output.
+//
+// A checker is run as `checker input expected actual`, each argument naming a
+// file, inside the same sandbox as submissions. Its exit code is the verdict:
+// 0 accepts the output, 1 rejects it, anything else means the checker itself
+// failed. The first line of its stdout may be a score between 0 and 1; the rest
+// is a message shown with the result.
+
+use std::path::Path;
+
+use crate::helper;
+use crate::profile::Profile;
+use crate::runner::Termination;
+use crate::sandbox::Sandbox;
+
+/// What a checker decided about one test's output.
+#[derive(Debug, Clone)]
+pub struct CheckerReport {
+    pub accepted: bool,
+    /// Partial credit between 0 and 1, if the checker printed one.
+    pub score: Option<f64>,
+    pub message: String,
+}
+
+/// Runs the checker at `checker` on one test's files, which are written to a
+/// `check` directory next to the submission's work directory.
+pub fn run(
+    jail: &dyn Sandbox,
+    checker: &Path,
+    submission_dir: &Path,
+    profile: Option<&Profile>,
+    input: &str,
+    expected: &str,
+    actual: &[u8],
+) -> Result<CheckerReport, String> {
+    let args = ["input.txt".to_string(), "expected.txt".to_string(), "actual.txt".to_string()];
+    let files: [(&str, &[u8]); 3] = [
+        ("input.txt", input.as_bytes()),
+        ("expected.txt", expected.as_bytes()),
+        ("actual.txt", actual),
+    ];
+    let outcome = helper::run(
+        jail,
+        checker,
+        &submission_dir.join("check"),
+        &args,
+        &files,
+        profile,
+        &submission_dir.join("checker.log"),
+    )?;
+
+    let stdout = String::from_utf8_lossy(&outcome.stdout);
+    let (first_line, rest) = stdout.split_once('\n').unwrap_or((&stdout, ""));
+    let (score, message) = match first_line.trim().parse::<f64>() {
+        Ok(score) if (0.0..=1.0).contains(&score) => (Some(score), rest.trim().to_string()),
+        Ok(score) => return Err(format!("checker printed score {}, which is not between 0 and 1", score)),
+        Err(_) => (None, stdout.trim().to_string()),
+    };
+    match jail.termination(&outcome) {
+        Termination::Exited(0) => Ok(CheckerReport { accepted: true, score, message }),
+        Termination::Exited(1) => Ok(CheckerReport { accepted: false, score, message }),
+        other => {
+            let stderr = String::from_utf8_lossy(&outcome.stderr);
+            Err(format!("checker ended with {}: {}", other.describe(), stderr.trim()))
+        }
+    }
+}
@@ -0,0 +1,89 @@
+// Compiling C sources with gcc inside the sandbox.
+
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::profile::Profile;
+use crate::runner::Limits;
+use crate::sandbox::{self, Job, Sandbox};
+
+/// Why a source file could not be turned into an executable.
+#[derive(Debug)]
+pub enum CompileError {
+    /// The sandbox could not run the compiler at all.
+    Sandbox(String),
+    TimedOut(Duration),
+    /// gcc rejected the source; holds its diagnostics.
+    Errors(String),
+    /// gcc reported success but left no executable behind.
+    MissingExecutable,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::Sandbox(e) => write!(f, "{}", e),
+            CompileError::TimedOut(limit) => write!(f, "compilation timed out after {:.1} seconds", limit.as_secs_f64()),
+            CompileError::Errors(errors) => write!(f, "{}", errors),
+            CompileError::MissingExecutable => write!(f, "no executable was produced"),
+        }
+    }
+}
+
+/// A successfully compiled source file.
+#[derive(Debug)]
+pub struct Compiled {
+    pub executable: PathBuf,
+    /// Whatever gcc printed, such as warnings.
+    pub diagnostics: String,
+}
+
+/// Compiles `main.c` in `work_dir` into `main` next to it. Only the toolchain
+/// and `work_dir` are visible to gcc. The executable is linked statically, so
+/// that it runs without any system directory mounted.
+pub fn compile(
+    jail: &dyn Sandbox,
+    work_dir: &Path,
+    limits: &Limits,
+    profile: Option<&Profile>,
+    log_file: &Path,
+) -> Result<Compiled, CompileError> {
+    let exe_path = work_dir.join("main");
+    let args = ["-static".to_string(), "-o".to_string(), "main".to_string(), "main.c".to_string()];
+    let mounts = sandbox::compiler_mounts(work_dir);
+    let env = [
+        ("PATH", "/usr/bin:/bin".to_string()),
+        ("TMPDIR", work_dir.to_string_lossy().into_owned()),
+    ];
+    let job = Job {
+        program: Path::new("/usr/bin/gcc"),
+        args: &args,
+        mounts: &mounts,
+        cwd: Some(work_dir),
+        env: &env,
+        limits,
+        profile,
+        seccomp_policy: None,
+        log_file: Some(log_file),
+    };
+    let output = jail.run(&job, b"").map_err(|e| CompileError::Sandbox(e.to_string()))?;
+    if output.exceeded_time(limits) {
+        return Err(CompileError::TimedOut(limits.wall_time));
+    }
+    if !output.status.success() {
+        return Err(CompileError::Errors(String::from_utf8_lossy(&output.stderr).into_owned()));
+    }
+
+    // Set executable permissions explicitly.
+    if let Err(e) = fs::set_permissions(&exe_path, fs::Permissions::from_mode(0o755)) {
+        eprintln!("Error setting permissions on executable: {}", e);
+    }
+    if !exe_path.exists() {
+        eprintln!("Executable not found at: {}", exe_path.display());
+        return Err(CompileError::MissingExecutable);
+    }
+    Ok(Compiled { executable: exe_path, diagnostics: String::from_utf8_lossy(&output.stderr).into_owned() })
+}
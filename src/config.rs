@@ -0,0 +1,134 @@
+// Grader settings read from Rocket's configuration (`rocket.toml` or `ROCKET_*` variables).
+
+use rocket::fairing::AdHoc;
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::auth::{self, ApiLogins};
+use crate::profile::Profiles;
+use crate::questions;
+use crate::reference::References;
+use crate::seccomp;
+use crate::sandbox::{Backend, Sandbox};
+use crate::store::Store;
+
+/// Settings beyond Rocket's own, read from the same configuration sources.
+#[derive(Deserialize, Debug)]
+pub struct GraderConfig {
+    /// Which sandbox compiles and runs submissions.
+    #[serde(default)]
+    pub sandbox: Backend,
+    /// Sandbox profile (`.toml` or nsjail `.cfg`) for questions without their own.
+    pub sandbox_profile: Option<String>,
+    /// SQLite database every submission is recorded in.
+    #[serde(default = "default_database")]
+    pub database: String,
+    /// Admin accounts, from username to the argon2 hash of their password as a PHC string.
+    #[serde(default)]
+    pub admins: BTreeMap<String, String>,
+    /// How long an admin login lasts.
+    #[serde(default = "default_admin_session_minutes")]
+    pub admin_session_minutes: u64,
+}
+
+fn default_database() -> String {
+    "submissions.db".to_string()
+}
+
+fn default_admin_session_minutes() -> u64 {
+    120
+}
+
+/// The key this file's documentation once shipped with, which is public.
+const PLACEHOLDER_SECRET_KEY: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+/// Checks that the secret key comes from the environment. Anyone who knows it
+/// can forge session cookies, including an admin's, so it must not be a value
+/// from a file in the repository.
+fn validate_secret_key() -> Result<(), String> {
+    match env::var("ROCKET_SECRET_KEY") {
+        Ok(key) if key.trim() == PLACEHOLDER_SECRET_KEY => {
+            Err("ROCKET_SECRET_KEY is the published placeholder; generate a new one with `openssl rand -base64 32`".to_string())
+        }
+        Ok(key) if !key.trim().is_empty() => Ok(()),
+        _ => Err("ROCKET_SECRET_KEY is not set; generate one with `openssl rand -base64 32`".to_string()),
+    }
+}
+
+/// Checks that every admin's password hash can be read, so that a mistyped
+/// hash stops the launch instead of locking that admin out.
+fn validate_admins(config: &GraderConfig) -> Result<(), String> {
+    if config.admins.is_empty() {
+        println!("Warning: no admins are configured, so nobody can log in to /admin");
+    }
+    for (username, hash) in &config.admins {
+        auth::validate_hash(hash).map_err(|e| format!("Admin {}: {}", username, e))?;
+    }
+    Ok(())
+}
+
+/// Loads the default profile and every profile a question names, and checks each
+/// question's seccomp settings, reference solution and generator, so that a bad profile stops
+/// the launch instead of failing each submission.
+fn load_profiles(config: &GraderConfig) -> Result<Profiles, String> {
+    let profiles = Profiles::new(config.sandbox, config.sandbox_profile.as_deref())?;
+    // A missing or broken test cases file is reported per submission as before.
+    for (id, question) in questions::load().unwrap_or_default() {
+        if let Some(path) = &question.settings.sandbox_profile {
+            profiles.get(path)?;
+        }
+        seccomp::validate(&question.settings.allowed_syscalls)
+            .map_err(|e| format!("Question {}: {}", id, e))?;
+        question.settings.validate().map_err(|e| format!("Question {}: {}", id, e))?;
+    }
+    Ok(profiles)
+}
+
+/// Reads and checks [`GraderConfig`] and manages it together with the chosen sandbox
+/// backend, its profiles, the cache of compiled reference solutions, the
+/// submissions database and the API's record of logins.
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Grader Config", |rocket| async {
+        let config: GraderConfig = match rocket.figment().extract() {
+            Ok(config) => config,
+            Err(e) => {
+                rocket::config::pretty_print_error(e);
+                return Err(rocket);
+            }
+        };
+        if let Err(e) = validate_secret_key().and_then(|_| validate_admins(&config)) {
+            eprintln!("Error: {}", e);
+            return Err(rocket);
+        }
+        let profiles = match load_profiles(&config) {
+            Ok(profiles) => profiles,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return Err(rocket);
+            }
+        };
+        let store = match Store::open(Path::new(&config.database)) {
+            Ok(store) => store,
+            Err(e) => {
+                eprintln!("Error: cannot open database {}: {}", config.database, e);
+                return Err(rocket);
+            }
+        };
+        // Shared with the blocking threads submissions are graded on.
+        let sandbox: Arc<dyn Sandbox> = Arc::from(config.sandbox.build());
+        println!("Using the {} sandbox backend", sandbox.name());
+        let cwd = env::current_dir().expect("Failed to get current directory");
+        let references = References::new(cwd.join("tempfiles").join("references"));
+        Ok(rocket
+            .manage(sandbox)
+            .manage(Arc::new(profiles))
+            .manage(Arc::new(references))
+            .manage(Arc::new(store))
+            .manage(ApiLogins::default())
+            .manage(config))
+    })
+}
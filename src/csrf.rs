@@ -0,0 +1,94 @@
+// Protection against cross-site request forgery on forms that change state.
+//
+// Each session gets a random token in a private cookie the first time a page
+// with a form is rendered for it, and every such form carries the token in a
+// hidden `csrf_token` field. The token is discarded whenever someone logs in or
+// out, so one set before a login is not valid in the session that follows. A
+// POST handler takes its form as a `CsrfForm`, which rejects the request with
+// 403 Forbidden unless the field matches the cookie. Forms default the field to
+// empty, so that a missing token is rejected the same way as a wrong one.
+// Another site can make the browser send the cookie, but it cannot read the
+// token to put in the form.
+
+use rocket::data::{Data, FromData, Outcome};
+use rocket::form::{self, Form, FromForm};
+use rocket::http::{Cookie, CookieJar, Status};
+use rocket::request::Request;
+use std::ops::{Deref, DerefMut};
+
+use crate::auth;
+
+/// Private cookie holding the session's token.
+const CSRF_COOKIE: &str = "csrf_token";
+
+/// A form with a `csrf_token` field.
+pub trait CsrfProtected {
+    fn csrf_token(&self) -> &str;
+}
+
+/// The session's token, creating it if this is its first form.
+pub fn token(cookies: &CookieJar<'_>) -> String {
+    if let Some(cookie) = cookies.get_private(CSRF_COOKIE) {
+        return cookie.value().to_string();
+    }
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    cookies.add_private(Cookie::new(CSRF_COOKIE, token.clone()));
+    token
+}
+
+/// The hidden field to put in every form that is posted.
+pub fn field(cookies: &CookieJar<'_>) -> String {
+    format!("<input type=\"hidden\" name=\"csrf_token\" value=\"{}\">", token(cookies))
+}
+
+/// Discards the token, so that the next form gets a new one.
+pub fn reset(cookies: &CookieJar<'_>) {
+    cookies.remove_private(CSRF_COOKIE);
+}
+
+/// Whether `submitted` is the session's token.
+fn matches(cookies: &CookieJar<'_>, submitted: &str) -> bool {
+    let Some(cookie) = cookies.get_private(CSRF_COOKIE) else {
+        return false;
+    };
+    auth::constant_time_eq(cookie.value().as_bytes(), submitted.as_bytes())
+}
+
+/// Set on a request whose form was rejected, so that the 403 page can say why.
+pub struct CsrfRejected(pub bool);
+
+/// Form data whose `csrf_token` matched the session's token.
+pub struct CsrfForm<T>(T);
+
+impl<T> Deref for CsrfForm<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for CsrfForm<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T: FromForm<'r> + CsrfProtected> FromData<'r> for CsrfForm<T> {
+    type Error = form::Errors<'r>;
+
+    async fn from_data(request: &'r Request<'_>, data: Data<'r>) -> Outcome<'r, Self> {
+        match Form::<T>::from_data(request, data).await {
+            Outcome::Success(form) if matches(request.cookies(), form.csrf_token()) => {
+                Outcome::Success(CsrfForm(form.into_inner()))
+            }
+            Outcome::Success(_) => {
+                request.local_cache(|| CsrfRejected(true));
+                Outcome::Error((Status::Forbidden, form::Error::validation("invalid CSRF token").into()))
+            }
+            Outcome::Error(e) => Outcome::Error(e),
+            Outcome::Forward(forward) => Outcome::Forward(forward),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+// Randomized inputs generated for every submission, judged against the
+// question's reference solution.
+//
+// Inputs come either from a built-in spec, such as "15 floats in [-100, 100]",
+// or from a script that prints one input given a seed. Every submission gets a
+// fresh seed, which is shown with the results so that a failure can be
+// reproduced by submitting again with the same seed.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::helper;
+use crate::matcher::Matcher;
+use crate::profile::Profile;
+use crate::questions::TestCase;
+use crate::runner::Termination;
+use crate::sandbox::Sandbox;
+
+/// A question's input generator, from the `generator` key of its settings.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Generator {
+    #[serde(flatten)]
+    pub source: InputSource,
+    /// Generated test cases per submission.
+    #[serde(default = "default_cases")]
+    pub cases: usize,
+    /// How the output of generated cases is compared with the reference's.
+    #[serde(default, skip_serializing_if = "Matcher::is_default")]
+    pub matcher: Matcher,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abs_tolerance: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rel_tolerance: Option<f64>,
+}
+
+/// Where generated inputs come from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum InputSource {
+    /// `count` integers between `min` and `max` inclusive, separated by spaces.
+    Ints { count: usize, min: i64, max: i64 },
+    /// `count` numbers between `min` and `max`, printed with `decimals` digits.
+    Floats {
+        count: usize,
+        min: f64,
+        max: f64,
+        #[serde(default = "default_decimals")]
+        decimals: usize,
+    },
+    /// A program run as `path seed`, whose stdout is the input.
+    Script { path: String },
+}
+
+fn default_cases() -> usize {
+    5
+}
+
+fn default_decimals() -> usize {
+    2
+}
+
+/// A fresh seed for a submission.
+pub fn random_seed() -> u64 {
+    uuid::Uuid::new_v4().as_u64_pair().0
+}
+
+impl Generator {
+    /// Checks the spec's ranges, or that the script exists.
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.source {
+            InputSource::Ints { min, max, .. } if min > max => {
+                Err(format!("Generator range [{}, {}] is empty", min, max))
+            }
+            InputSource::Floats { min, max, .. } if min > max || !min.is_finite() || !max.is_finite() => {
+                Err(format!("Generator range [{}, {}] is empty", min, max))
+            }
+            InputSource::Script { path } if !Path::new(path).is_file() => {
+                Err(format!("Generator script {} does not exist", path))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The generated test cases for a submission, with empty expected outputs
+    /// for the reference solution to fill in. Case `i` uses seed `seed + i`.
+    pub fn test_cases(
+        &self,
+        seed: u64,
+        jail: &dyn Sandbox,
+        submission_dir: &Path,
+        profile: Option<&Profile>,
+    ) -> Result<Vec<TestCase>, String> {
+        (0..self.cases)
+            .map(|i| {
+                let input = self.input(seed.wrapping_add(i as u64), jail, submission_dir, profile)?;
+                Ok(TestCase {
+                    description: format!("Random input {} of {}", i + 1, self.cases),
+                    input,
+                    group: Some("Random inputs".to_string()),
+                    matcher: self.matcher,
+                    abs_tolerance: self.abs_tolerance,
+                    rel_tolerance: self.rel_tolerance,
+                    ..TestCase::default()
+                })
+            })
+            .collect()
+    }
+
+    fn input(&self, seed: u64, jail: &dyn Sandbox, submission_dir: &Path, profile: Option<&Profile>) -> Result<String, String> {
+        let mut rng = Rng(seed);
+        match &self.source {
+            InputSource::Ints { count, min, max } => {
+                let values: Vec<String> = (0..*count).map(|_| rng.int(*min, *max).to_string()).collect();
+                Ok(values.join(" "))
+            }
+            InputSource::Floats { count, min, max, decimals } => {
+                let values: Vec<String> = (0..*count)
+                    .map(|_| format!("{:.*}", decimals, min + rng.float() * (max - min)))
+                    .collect();
+                Ok(values.join(" "))
+            }
+            InputSource::Script { path } => {
+                let outcome = helper::run(
+                    jail,
+                    Path::new(path),
+                    &submission_dir.join("generate"),
+                    &[seed.to_string()],
+                    &[],
+                    profile,
+                    &submission_dir.join("generator.log"),
+                )?;
+                match jail.termination(&outcome) {
+                    Termination::Exited(0) => Ok(String::from_utf8_lossy(&outcome.stdout).into_owned()),
+                    other => Err(format!(
+                        "Generator {} ended with {} for seed {}: {}",
+                        path,
+                        other.describe(),
+                        seed,
+                        String::from_utf8_lossy(&outcome.stderr).trim()
+                    )),
+                }
+            }
+        }
+    }
+}
+
+/// SplitMix64: small, fast and the same on every platform, so a seed always
+/// produces the same inputs.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1).
+    fn float(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in [min, max].
+    fn int(&mut self, min: i64, max: i64) -> i64 {
+        let span = (max as i128 - min as i128 + 1) as u128;
+        (min as i128 + (self.next_u64() as u128 % span) as i128) as i64
+    }
+}
@@ -0,0 +1,301 @@
+// Per-test verdicts and results shown on the results page.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::checker::CheckerReport;
+use crate::interaction::Transcript;
+use crate::questions::TestCase;
+use crate::runner::{signal_name, Limits, RunOutcome, Termination};
+use crate::sandbox::Sandbox;
+
+/// Outcome of running a submission against a single test case.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", content = "reason", rename_all = "snake_case")]
+pub enum Verdict {
+    Passed,
+    Failed,
+    TimeLimitExceeded,
+    MemoryLimitExceeded,
+    OutputLimitExceeded,
+    /// Killed by the seccomp policy for making the named system call.
+    ForbiddenSyscall(String),
+    /// Crashed, or exited with a code other than the expected one.
+    RuntimeError(String),
+    /// The test's checker program failed, so the output could not be judged.
+    CheckerError(String),
+}
+
+impl Verdict {
+    pub fn passed(&self) -> bool {
+        *self == Verdict::Passed
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::Passed => write!(f, "Passed"),
+            Verdict::Failed => write!(f, "Failed"),
+            Verdict::TimeLimitExceeded => write!(f, "Time Limit Exceeded"),
+            Verdict::MemoryLimitExceeded => write!(f, "Memory Limit Exceeded"),
+            Verdict::OutputLimitExceeded => write!(f, "Output Limit Exceeded"),
+            Verdict::ForbiddenSyscall(name) => write!(f, "Forbidden system call: {}", name),
+            Verdict::RuntimeError(reason) => write!(f, "Runtime Error ({})", reason),
+            Verdict::CheckerError(reason) => write!(f, "Checker Error ({})", reason),
+        }
+    }
+}
+
+/// Result of a single test case, ready to be rendered.
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    pub description: String,
+    pub verdict: Verdict,
+    pub details: String,
+    /// Resources used, if the program was started at all.
+    pub usage: Option<Usage>,
+    /// How the program ended, if it was started at all.
+    pub termination: Option<Termination>,
+    /// What the program wrote to stderr, truncated to the question's limit.
+    pub stderr: String,
+    /// Expected output files that are missing or differ.
+    pub file_diffs: Vec<FileDiff>,
+    /// The conversation, for interactive tests.
+    pub transcript: Option<Transcript>,
+    /// Partial credit between 0 and 1 awarded by the test's checker, if any.
+    pub score: Option<f64>,
+}
+
+/// An output file whose contents differ from the test's expectation.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileDiff {
+    pub name: String,
+    /// Line-by-line diff, with "- " before expected and "+ " before actual lines.
+    pub diff: String,
+}
+
+/// Resources a test run consumed.
+#[derive(Debug, Clone, Copy)]
+pub struct Usage {
+    pub cpu_time: Duration,
+    pub wall_time: Duration,
+    /// Peak resident memory, in bytes.
+    pub memory: u64,
+}
+
+impl From<&RunOutcome> for Usage {
+    fn from(outcome: &RunOutcome) -> Self {
+        Usage { cpu_time: outcome.cpu_time, wall_time: outcome.wall_time, memory: outcome.peak_memory }
+    }
+}
+
+impl TestResult {
+    /// A failure that happened before or around running the program.
+    pub fn error(description: &str, details: String) -> Self {
+        TestResult {
+            description: description.to_string(),
+            verdict: Verdict::Failed,
+            details,
+            usage: None,
+            termination: None,
+            stderr: String::new(),
+            file_diffs: Vec::new(),
+            transcript: None,
+            score: None,
+        }
+    }
+
+    /// Short "CPU …, wall …, memory …, exit code …" annotation for the results page.
+    pub fn usage_label(&self) -> String {
+        let mut label = match self.usage {
+            Some(u) => format!(
+                "CPU {:.3}s, wall {:.3}s, memory {:.1} MB",
+                u.cpu_time.as_secs_f64(),
+                u.wall_time.as_secs_f64(),
+                u.memory as f64 / (1024.0 * 1024.0)
+            ),
+            None => String::new(),
+        };
+        if let Some(termination) = self.termination {
+            label.push_str(", ");
+            label.push_str(&termination.describe());
+        }
+        label
+    }
+}
+
+/// Decides the verdict for a finished run of `case`. Being stopped by the
+/// sandbox or runner takes precedence over how the program ended, which takes
+/// precedence over its output. The output is judged by `checked`, the report of
+/// the test's checker, if it has one, and by its matcher otherwise. Expected
+/// output files are read from `run_dir`, where the program ran. An interactive
+/// test also fails if its `transcript` diverged from the script.
+pub fn judge(
+    case: &TestCase,
+    limits: &Limits,
+    outcome: &RunOutcome,
+    sandbox: &dyn Sandbox,
+    checked: Option<&Result<CheckerReport, String>>,
+    run_dir: &Path,
+    transcript: Option<Transcript>,
+) -> TestResult {
+    let termination = sandbox.termination(outcome);
+    let actual_output = String::from_utf8_lossy(&outcome.stdout).into_owned();
+    let output_check = match checked {
+        Some(Ok(report)) if report.accepted => Ok(()),
+        Some(Ok(report)) => Err(report.message.clone()),
+        Some(Err(_)) => Ok(()),
+        None => case.matcher.check(&case.expected_output, &actual_output, &case.tolerance()),
+    };
+    let stderr = String::from_utf8_lossy(&outcome.stderr).into_owned();
+    let stderr_matches = match &case.expected_stderr {
+        Some(expected) => stderr.contains(expected.trim()),
+        None => true,
+    };
+    let file_diffs = compare_files(case, run_dir, limits.file_size);
+    let wrong_exit_code = match (case.expected_exit_code, termination) {
+        (Some(expected), Termination::Exited(code)) if code != expected => {
+            Some(format!("exit code {}, expected {}", code, expected))
+        }
+        _ => None,
+    };
+    let verdict = if let Some(syscall) = sandbox.blocked_syscall(outcome) {
+        Verdict::ForbiddenSyscall(syscall)
+    } else if outcome.killed_on_output {
+        Verdict::OutputLimitExceeded
+    } else if outcome.exceeded_time(limits) {
+        Verdict::TimeLimitExceeded
+    } else if outcome.exceeded_memory(limits) {
+        Verdict::MemoryLimitExceeded
+    } else if let Termination::Signaled(signal) = termination {
+        Verdict::RuntimeError(signal_name(signal).to_string())
+    } else if let Some(reason) = wrong_exit_code {
+        Verdict::RuntimeError(reason)
+    } else if let Some(Err(reason)) = checked {
+        Verdict::CheckerError(reason.clone())
+    } else if transcript.as_ref().is_some_and(|t| t.divergence.is_some()) {
+        Verdict::Failed
+    } else if output_check.is_ok() && stderr_matches && file_diffs.is_empty() {
+        Verdict::Passed
+    } else {
+        Verdict::Failed
+    };
+    let expectation = if checked.is_some() { "Expected (judged by the checker)" } else { case.matcher.expectation() };
+    let mut details = format!(
+        "Input: {}\n{}: {}\nGot: {}",
+        case.input,
+        expectation,
+        case.expected_output.trim(),
+        actual_output.trim()
+    );
+    match checked {
+        Some(Ok(report)) if !report.message.is_empty() => {
+            details.push_str(&format!("\nChecker: {}", report.message));
+        }
+        Some(Ok(_)) | Some(Err(_)) => {}
+        None => {
+            if let Err(difference) = &output_check {
+                details.push_str(&format!("\nDifference: {}", difference));
+            }
+        }
+    }
+    let score = match checked {
+        Some(Ok(report)) => report.score,
+        _ => None,
+    };
+    if let Some(score) = score {
+        details.push_str(&format!("\nScore: {:.2}", score));
+    }
+    if let Some(expected) = &case.expected_stderr {
+        details.push_str(&format!("\nExpected stderr to contain: {}", expected.trim()));
+    }
+    for diff in &file_diffs {
+        details.push_str(&format!("\nOutput file {} does not match", diff.name));
+    }
+    if let Some(divergence) = transcript.as_ref().and_then(|t| t.divergence.as_ref()) {
+        details.push_str(&format!("\nInteraction step {}: {}", divergence.step + 1, divergence.reason));
+    }
+    TestResult {
+        description: case.description.clone(),
+        verdict,
+        details,
+        usage: Some(outcome.into()),
+        termination: Some(termination),
+        stderr,
+        file_diffs,
+        transcript,
+        score,
+    }
+}
+
+/// Compares each of the test's expected files with the one the program left in
+/// `dir`, reading at most `max_bytes` of each.
+fn compare_files(case: &TestCase, dir: &Path, max_bytes: u64) -> Vec<FileDiff> {
+    case.expected_files
+        .iter()
+        .filter_map(|(name, expected)| {
+            let diff = match read_output_file(&dir.join(name), max_bytes) {
+                Ok(actual) => {
+                    let actual = String::from_utf8_lossy(&actual);
+                    if actual == *expected {
+                        return None;
+                    }
+                    line_diff(expected, &actual)
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => format!("The program did not write {}", name),
+                Err(e) => format!("Cannot read the {} the program wrote: {}", name, e),
+            };
+            Some(FileDiff { name: name.clone(), diff })
+        })
+        .collect()
+}
+
+/// Reads a file the program wrote, up to `max_bytes`. The program controls the
+/// directory, so anything but a regular file is refused: a symlink could point
+/// at a file of the grader's, and reading a FIFO would block forever.
+fn read_output_file(path: &Path, max_bytes: u64) -> io::Result<Vec<u8>> {
+    let not_regular = || io::Error::new(io::ErrorKind::InvalidInput, "not a regular file");
+    if !fs::symlink_metadata(path)?.file_type().is_file() {
+        return Err(not_regular());
+    }
+    // The file may have been swapped since it was checked, so opening it must not
+    // follow a symlink or wait for a FIFO's writer either.
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)?;
+    if !file.metadata()?.file_type().is_file() {
+        return Err(not_regular());
+    }
+    let mut contents = Vec::new();
+    file.take(max_bytes).read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// Lines of `expected` and `actual` side by side: unchanged lines are indented,
+/// differing ones shown as a "- expected" and "+ actual" pair.
+fn line_diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.split('\n').collect();
+    let actual: Vec<&str> = actual.split('\n').collect();
+    let mut diff = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => diff.push_str(&format!("  {}\n", e)),
+            (e, a) => {
+                if let Some(e) = e {
+                    diff.push_str(&format!("- {}\n", e));
+                }
+                if let Some(a) = a {
+                    diff.push_str(&format!("+ {}\n", a));
+                }
+            }
+        }
+    }
+    diff
+}
@@ -0,0 +1,67 @@
+// Instructor-provided helper programs, such as checkers and input generators,
+// run in the sandbox next to a submission.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::profile::Profile;
+use crate::runner::{Limits, RunOutcome};
+use crate::sandbox::{self, Job, Sandbox};
+
+/// Helpers are trusted, but still get fixed limits so a buggy one can't hang grading.
+pub const HELPER_LIMITS: Limits = Limits {
+    wall_time: Duration::from_secs(5),
+    cpu_time: Duration::from_secs(5),
+    memory: 256 * 1024 * 1024,
+    processes: 16,
+    file_size: 1024 * 1024,
+    output: 64 * 1024,
+    stderr: 16 * 1024,
+};
+
+/// Copies `program` into a fresh `dir` together with `files` (name and
+/// contents), runs it there with `args` and removes `dir` again. Scripts may use
+/// any interpreter from the toolchain directories.
+pub fn run(
+    jail: &dyn Sandbox,
+    program: &Path,
+    dir: &Path,
+    args: &[String],
+    files: &[(&str, &[u8])],
+    profile: Option<&Profile>,
+    log_file: &Path,
+) -> Result<RunOutcome, String> {
+    let copy = dir.join("helper");
+    let prepare = || -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        fs::copy(program, &copy)?;
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents)?;
+        }
+        Ok(())
+    };
+    prepare().map_err(|e| format!("Cannot prepare {}: {}", program.display(), e))?;
+
+    let mounts = sandbox::compiler_mounts(dir);
+    let env = [("PATH", "/usr/bin:/bin".to_string())];
+    let job = Job {
+        program: &copy,
+        args,
+        mounts: &mounts,
+        cwd: Some(dir),
+        env: &env,
+        limits: &HELPER_LIMITS,
+        profile,
+        seccomp_policy: None,
+        log_file: Some(log_file),
+    };
+    let outcome = jail.run(&job, b"");
+    let _ = fs::remove_dir_all(dir);
+    let outcome = outcome.map_err(|e| format!("Cannot run {} with {}: {}", program.display(), jail.name(), e))?;
+    if outcome.exceeded_time(&HELPER_LIMITS) {
+        return Err(format!("{} timed out", program.display()));
+    }
+    Ok(outcome)
+}
@@ -0,0 +1,136 @@
+// Scripted conversations with prompt-and-respond programs.
+//
+// Instead of writing all input up front, an interactive test alternates between
+// waiting for output matching a pattern and sending a line, so programs that
+// keep reading until a sentinel can be tested one prompt at a time.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::process::ChildStdin;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// How long a step waits for its expected output when it sets no timeout.
+const DEFAULT_STEP_TIMEOUT_MS: u64 = 1000;
+
+/// One step of an interactive test: wait for output matching `expect`, if set,
+/// then send `send`, if set, as a line of input.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Step {
+    /// Regular expression the program's next output must match. The match
+    /// consumes the output up to the end of the line it ends on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expect: Option<String>,
+    /// Line written to the program's stdin; a newline is appended.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+}
+
+/// Checks that every step's pattern compiles.
+pub fn validate(steps: &[Step]) -> Result<(), String> {
+    for (i, step) in steps.iter().enumerate() {
+        if let Some(pattern) = &step.expect {
+            Regex::new(pattern).map_err(|e| format!("step {}: invalid pattern: {}", i + 1, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Something that happened during a conversation.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    /// Text the program printed.
+    Output(String),
+    /// A line sent to the program.
+    Input(String),
+}
+
+/// Where a conversation went differently from its script.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Divergence {
+    /// Index of the step that failed.
+    pub step: usize,
+    /// Number of events before the divergence; the events after it are what
+    /// the program printed afterwards.
+    pub event: usize,
+    pub reason: String,
+}
+
+/// Everything said in a conversation, in order.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Transcript {
+    pub events: Vec<Event>,
+    /// Set if the program stopped following the script.
+    pub divergence: Option<Divergence>,
+}
+
+/// Plays `steps` against a running program, reading its output from `output`
+/// as it arrives. Once the script is done or has diverged, stdin is closed and
+/// the rest of the output is collected until the program's stdout closes.
+pub fn converse(mut stdin: ChildStdin, output: Receiver<Vec<u8>>, steps: &[Step]) -> Transcript {
+    let mut transcript = Transcript::default();
+    // Output received but not yet matched by a step.
+    let mut pending = String::new();
+    let mut closed = false;
+
+    for (i, step) in steps.iter().enumerate() {
+        if let Some(pattern) = &step.expect {
+            // Patterns are checked when the test is saved.
+            let Ok(re) = Regex::new(pattern) else { break };
+            let timeout = Duration::from_millis(step.timeout_ms.unwrap_or(DEFAULT_STEP_TIMEOUT_MS));
+            let deadline = Instant::now() + timeout;
+            let matched = loop {
+                if let Some(m) = re.find(&pending) {
+                    // The rest of the line belongs to the same prompt or answer.
+                    break Ok(pending[m.end()..].find('\n').map_or(m.end(), |i| m.end() + i + 1));
+                }
+                if closed {
+                    break Err("the program's output ended".to_string());
+                }
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                match output.recv_timeout(remaining) {
+                    Ok(chunk) => pending.push_str(&String::from_utf8_lossy(&chunk)),
+                    Err(RecvTimeoutError::Timeout) => {
+                        break Err(format!("got no such output within {} ms", timeout.as_millis()))
+                    }
+                    Err(RecvTimeoutError::Disconnected) => closed = true,
+                }
+            };
+            match matched {
+                Ok(end) => {
+                    let rest = pending.split_off(end);
+                    transcript.events.push(Event::Output(std::mem::replace(&mut pending, rest)));
+                }
+                Err(reason) => {
+                    let reason = format!("expected output matching /{}/, but {}", pattern, reason);
+                    transcript.divergence = Some(Divergence { step: i, event: transcript.events.len(), reason });
+                    break;
+                }
+            }
+        }
+        if let Some(line) = &step.send {
+            if stdin.write_all(format!("{}\n", line).as_bytes()).is_err() {
+                transcript.divergence = Some(Divergence {
+                    step: i,
+                    event: transcript.events.len(),
+                    reason: format!("could not send {:?}: the program stopped reading its input", line),
+                });
+                break;
+            }
+            transcript.events.push(Event::Input(line.clone()));
+        }
+    }
+
+    drop(stdin);
+    while let Ok(chunk) = output.recv() {
+        pending.push_str(&String::from_utf8_lossy(&chunk));
+    }
+    if !pending.is_empty() {
+        transcript.events.push(Event::Output(pending));
+    }
+    transcript
+}
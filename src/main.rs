@@ -1,28 +1,50 @@
 // Import Rocket macros and external crates.
 #[macro_use] extern crate rocket;
 
-use rocket::form::Form;
 use rocket::fs::{TempFile, FileServer, relative};
+use rocket::http::CookieJar;
+use rocket::response::Redirect;
 use rocket::response::content::RawHtml;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::process::{Command, Stdio};
-use std::io::Write;
+use rocket::State;
 use std::fs;
 use std::env;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
 
-/// Data structure representing a single test case.
-/// Each test case includes a description, input string, and expected output.
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct TestCase {
-    description: String,
-    input: String,
-    expected_output: String,
-}
+mod api;
+mod auth;
+mod checker;
+mod compiler;
+mod config;
+mod csrf;
+mod generator;
+mod grading;
+mod helper;
+mod interaction;
+mod matcher;
+mod profile;
+mod questions;
+mod reference;
+mod roster;
+mod runner;
+mod sandbox;
+mod scoring;
+mod seccomp;
+mod store;
 
-/// Type alias for a mapping from question IDs to lists of test cases.
-type TestCasesMap = HashMap<String, Vec<TestCase>>;
+use auth::{Instructor, Role, Staff, User};
+use config::GraderConfig;
+use csrf::{CsrfForm, CsrfProtected, CsrfRejected};
+use compiler::CompileError;
+use grading::TestResult;
+use scoring::Score;
+use interaction::{Event, Transcript};
+use profile::Profiles;
+use questions::{Question, TestCase, TestCasesMap, Visibility};
+use reference::References;
+use sandbox::Sandbox;
+use store::{Store, StoredSubmission};
 
 //
 // Routes for Uploading and Testing Code
@@ -34,6 +56,17 @@ type TestCasesMap = HashMap<String, Vec<TestCase>>;
 struct Upload<'r> {
     file: TempFile<'r>,
     question: String,
+    /// Seed for the question's random inputs, to reproduce an earlier submission.
+    /// Only instructors may choose it; students always get random inputs.
+    seed: Option<u64>,
+    #[field(default = String::new())]
+    csrf_token: String,
+}
+
+impl CsrfProtected for Upload<'_> {
+    fn csrf_token(&self) -> &str {
+        &self.csrf_token
+    }
 }
 
 /// GET /
@@ -41,7 +74,15 @@ struct Upload<'r> {
 /// The page also includes general website info about the autograder and a dynamic
 /// description of the selected question.
 #[get("/")]
-async fn index() -> RawHtml<&'static str> {
+async fn index(student: User, cookies: &CookieJar<'_>) -> RawHtml<String> {
+    let staff_link = if student.role >= Role::Ta { "<a href='/admin'>Staff panel</a> &middot; " } else { "" };
+    let session_html = format!(
+        "<p class='text-end'>Logged in as {} ({}, {}) &middot; {}<a href='/logout'>Log out</a></p>",
+        htmlescape::encode_minimal(&student.name),
+        htmlescape::encode_minimal(&student.id),
+        student.role,
+        staff_link
+    );
     RawHtml(r##"
 <!DOCTYPE html>
 <html>
@@ -58,11 +99,13 @@ async fn index() -> RawHtml<&'static str> {
   </head>
   <body>
     <div class="container mt-5">
+      <!-- session -->
       <div class="header">
         <h1>Autograder</h1>
         <p class="info">Welcome to the Autograder! Upload your C code to get instant feedback based on predefined test cases.</p>
       </div>
       <form action="/upload" method="post" enctype="multipart/form-data">
+        <!-- csrf -->
         <div class="mb-3">
           <label for="question" class="form-label">Select Question:</label>
           <select id="question" name="question" class="form-select">
@@ -97,6 +140,7 @@ async fn index() -> RawHtml<&'static str> {
           <input type="file" class="form-control" id="file" name="file" accept=".c">
         </div>
         <button type="submit" class="btn btn-primary">Submit</button>
+        <a href="/submissions" class="btn btn-link">Your submissions</a>
       </form>
     </div>
     <script>
@@ -135,178 +179,590 @@ async fn index() -> RawHtml<&'static str> {
     </script>
   </body>
 </html>
-"##)
+"##.replace("<!-- session -->", &session_html).replace("<!-- csrf -->", &csrf::field(cookies)))
 }
 
-/// POST /upload
-/// Handles the file upload, compiles the C code, loads test cases, and runs them
-/// inside a sandboxed environment using NSJail. Displays a test summary along with
-/// individual test results.
-#[post("/upload", data = "<form>")]
-async fn upload(mut form: Form<Upload<'_>>) -> RawHtml<String> {
-    use uuid::Uuid;
+/// GET /, without a session.
+#[get("/", rank = 2)]
+async fn index_logged_out() -> Redirect {
+    Redirect::to(uri!(login_page))
+}
 
-    // Check if a file was uploaded.
-    if form.file.name().is_none() {
-        return RawHtml("<h2>No file uploaded. Try again with a valid .c file</h2>".to_string());
+/// Error page for a submission that did not compile.
+fn compile_error_html(error: &CompileError) -> String {
+    match error {
+        CompileError::Sandbox(e) => format!("<h2>Compilation failed: {}</h2>", htmlescape::encode_minimal(e)),
+        CompileError::TimedOut(limit) => format!(
+            "<h2>Compilation timed out</h2><p>The compiler did not finish within {:.1} seconds.</p>",
+            limit.as_secs_f64()
+        ),
+        CompileError::Errors(err_msg) => {
+            format!("<h2>Compilation errors:</h2><pre>{}</pre>", htmlescape::encode_minimal(err_msg))
+        }
+        CompileError::MissingExecutable => "<h2>Internal error: compiled executable not found.</h2>".to_string(),
     }
+}
 
-    // Build an absolute path for the temporary directory.
-    let cwd = env::current_dir().expect("Failed to get current directory");
-    let temp_dir: PathBuf = cwd.join("tempfiles");
-
-    // Create the tempfiles directory if it doesn't exist.
-    if let Err(e) = fs::create_dir_all(&temp_dir) {
-        return RawHtml(format!("<h2>Error creating temp directory: {}</h2>", e));
+/// Transcript of an interactive test: the program's output as printed, the
+/// lines sent to it in bold, and a marker where it stopped following the script.
+fn transcript_html(transcript: &Transcript) -> String {
+    let mut html = String::new();
+    for (i, event) in transcript.events.iter().enumerate() {
+        if let Some(divergence) = transcript.divergence.as_ref().filter(|d| d.event == i) {
+            html.push_str(&format!("<mark>&#9888; {}</mark>\n", htmlescape::encode_minimal(&divergence.reason)));
+        }
+        match event {
+            Event::Output(text) => html.push_str(&htmlescape::encode_minimal(text)),
+            Event::Input(line) => html.push_str(&format!("<b style='color: #0d6efd;'>{}</b>\n", htmlescape::encode_minimal(line))),
+        }
+    }
+    if let Some(divergence) = transcript.divergence.as_ref().filter(|d| d.event == transcript.events.len()) {
+        html.push_str(&format!("<mark>&#9888; {}</mark>", htmlescape::encode_minimal(&divergence.reason)));
     }
+    format!(
+        "<details open><summary>Transcript</summary><pre style='background-color: #f1f1f1; color: #333; padding: 10px; border-radius: 5px; font-family: \"Courier New\", monospace;'>{}</pre></details>",
+        html
+    )
+}
 
-    // Generate unique file names using Uuid.
-    let unique_id = Uuid::new_v4().to_string();
-    let tmp_path = temp_dir.join(format!("{}.c", unique_id));
-    let exe_path = temp_dir.join(&unique_id);
+/// A graded submission, ready to be shown.
+struct GradedSubmission {
+    /// The ID it is recorded under.
+    id: String,
+    /// The tests that were run, including generated ones, with their expected outputs.
+    tests: Vec<TestCase>,
+    results: Vec<TestResult>,
+    score: Score,
+    /// Seed of the random inputs, if the question has a generator.
+    seed: Option<u64>,
+}
 
-    // Save the uploaded C file to disk.
-    if let Err(e) = form.file.persist_to(&tmp_path).await {
-        return RawHtml(format!("<h2>Error saving file: {}</h2>", e));
+impl From<StoredSubmission> for GradedSubmission {
+    fn from(stored: StoredSubmission) -> Self {
+        GradedSubmission {
+            id: stored.summary.id,
+            tests: stored.tests,
+            results: stored.results,
+            score: Score {
+                groups: stored.groups,
+                grade: stored.summary.grade.unwrap_or(0.0),
+                max_grade: stored.summary.max_grade.unwrap_or(0.0),
+            },
+            seed: stored.seed,
+        }
     }
+}
 
-    // Compile the C file using gcc.
-    let compile = Command::new("gcc")
-        .arg("-o")
-        .arg(&exe_path)
-        .arg(&tmp_path)
-        .output();
+/// Code to grade, from the upload form or the API.
+struct Attempt {
+    question: String,
+    source: Vec<u8>,
+    /// Seed for the question's random inputs, chosen by an instructor to
+    /// reproduce an earlier submission.
+    seed: Option<u64>,
+}
 
-    // Handle compilation errors.
-    let compile_output = match compile {
-        Ok(output) => output,
-        Err(e) => {
-            let _ = fs::remove_file(&tmp_path);
-            return RawHtml(format!("<h2>Compilation failed: {}</h2>", e));
-        }
-    };
+/// Students could otherwise fix the random inputs, read them from their results
+/// and hard-code the answers.
+const SEED_FORBIDDEN: &str = "Only instructors can choose the seed of the random inputs";
+
+/// Why a submission has no results.
+enum GradeError {
+    /// There are no test cases for the named question.
+    UnknownQuestion(String),
+    /// The code did not compile. The submission is recorded all the same.
+    Compile(CompileError),
+    /// Grading could not start or finish, and nothing was recorded.
+    Failed(String),
+}
 
-    if !compile_output.status.success() {
-        let err_msg = String::from_utf8_lossy(&compile_output.stderr);
-        let _ = fs::remove_file(&tmp_path);
-        return RawHtml(format!("<h2>Compilation errors:</h2><pre>{}</pre>", err_msg));
+impl GradeError {
+    /// The error page to show instead of the results.
+    fn html(&self) -> String {
+        match self {
+            GradeError::UnknownQuestion(question) => {
+                format!("<h2>No test cases found for question {}</h2>", htmlescape::encode_minimal(question))
+            }
+            GradeError::Compile(e) => compile_error_html(e),
+            GradeError::Failed(message) => format!("<h2>{}</h2>", htmlescape::encode_minimal(message)),
+        }
     }
+}
 
-    // Set executable permissions explicitly.
-    use std::os::unix::fs::PermissionsExt;
-    if let Err(e) = fs::set_permissions(&exe_path, fs::Permissions::from_mode(0o755)) {
-        eprintln!("Error setting permissions on executable: {}", e);
+/// The contents of the file uploaded with `form`.
+async fn read_upload(form: &Upload<'_>) -> Result<Vec<u8>, GradeError> {
+    if form.file.name().is_none() {
+        return Err(GradeError::Failed("No file uploaded. Try again with a valid .c file".to_string()));
     }
+    let mut source = Vec::new();
+    let read = match form.file.open().await {
+        Ok(mut file) => file.read_to_end(&mut source).await,
+        Err(e) => Err(e),
+    };
+    read.map_err(|e| GradeError::Failed(format!("Error reading file: {}", e)))?;
+    Ok(source)
+}
 
-    // Verify that the executable exists.
-    let exe_path_str = exe_path.to_string_lossy().into_owned();
-    if !Path::new(&exe_path).exists() {
-        eprintln!("Executable not found at: {}", exe_path_str);
-        return RawHtml("<h2>Internal error: compiled executable not found.</h2>".to_string());
+/// A directory removed with everything in it when this is dropped.
+struct RemoveOnDrop(PathBuf);
+
+impl Drop for RemoveOnDrop {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
     }
+}
+
+/// Grades `attempt` on a blocking thread, so that compiling and running it do
+/// not hold up the async workers serving every other request.
+async fn grade(
+    attempt: Attempt,
+    unique_id: &str,
+    student: Option<&str>,
+    jail: &State<Arc<dyn Sandbox>>,
+    profiles: &State<Arc<Profiles>>,
+    references: &State<Arc<References>>,
+    store: &State<Arc<Store>>,
+) -> Result<GradedSubmission, GradeError> {
+    let (unique_id, student) = (unique_id.to_string(), student.map(str::to_string));
+    let (jail, profiles, references, store) =
+        (Arc::clone(jail), Arc::clone(profiles), Arc::clone(references), Arc::clone(store));
+    let graded = rocket::tokio::task::spawn_blocking(move || {
+        grade_blocking(&attempt, &unique_id, student.as_deref(), jail.as_ref(), &profiles, &references, &store)
+    })
+    .await;
+    graded.unwrap_or_else(|e| Err(GradeError::Failed(format!("Grading stopped unexpectedly: {}", e))))
+}
 
+/// Compiles the submitted C code, loads test cases, and runs them inside the
+/// configured sandbox. Submissions that get as far as the compiler are recorded
+/// in `store`.
+fn grade_blocking(
+    attempt: &Attempt,
+    unique_id: &str,
+    student: Option<&str>,
+    jail: &dyn Sandbox,
+    profiles: &Profiles,
+    references: &References,
+    store: &Store,
+) -> Result<GradedSubmission, GradeError> {
     // Load test cases from the external JSON file.
-    let test_cases_data = match fs::read_to_string("test_cases.json") {
-        Ok(data) => data,
-        Err(e) => {
-            let _ = fs::remove_file(&tmp_path);
-            let _ = fs::remove_file(&exe_path);
-            return RawHtml(format!("<h2>Error reading test cases file: {}</h2>", e));
-        }
+    let test_cases_map = match questions::load() {
+        Ok(map) => map,
+        Err(e) => return Err(GradeError::Failed(e)),
     };
 
-    let test_cases_map: TestCasesMap = match serde_json::from_str(&test_cases_data) {
-        Ok(tc) => tc,
-        Err(e) => {
-            let _ = fs::remove_file(&tmp_path);
-            let _ = fs::remove_file(&exe_path);
-            return RawHtml(format!("<h2>Error parsing test cases file: {}</h2>", e));
-        }
+    let selected_question = attempt.question.as_str();
+    let question = match test_cases_map.get(selected_question) {
+        Some(q) => q,
+        None => return Err(GradeError::UnknownQuestion(selected_question.to_string())),
+    };
+    let profile = match profiles.for_question(question.settings.sandbox_profile.as_deref()) {
+        Ok(profile) => profile,
+        Err(e) => return Err(GradeError::Failed(e)),
     };
+    // Build an absolute path for the temporary directory.
+    let cwd = env::current_dir().expect("Failed to get current directory");
+    let temp_dir: PathBuf = cwd.join("tempfiles");
 
-    let selected_question = &form.question;
-    let cases = match test_cases_map.get(selected_question) {
-        Some(c) => c,
-        None => {
-            let _ = fs::remove_file(&tmp_path);
-            let _ = fs::remove_file(&exe_path);
-            return RawHtml(format!("<h2>No test cases found for question {}</h2>", selected_question));
+    // Each submission gets its own directory. Its `work` subdirectory is the only
+    // writable location the compiler and the program can see inside the jail; the
+    // sandbox's own log stays outside of it.
+    let submission_dir = temp_dir.join(unique_id);
+    // Removed again however grading ends.
+    let _cleanup = RemoveOnDrop(submission_dir.clone());
+    let work_dir = submission_dir.join("work");
+    let sandbox_log = submission_dir.join("sandbox.log");
+    if let Err(e) = fs::create_dir_all(&work_dir) {
+        return Err(GradeError::Failed(format!("Error creating temp directory: {}", e)));
+    }
+    // Random inputs are added to the question's own tests, and with a reference
+    // solution its output is the expected output of every test.
+    let mut tests = question.tests.clone();
+    let seed = attempt.seed.unwrap_or_else(generator::random_seed);
+    if let Some(generator) = &question.settings.generator {
+        match generator.test_cases(seed, jail, &submission_dir, profile.as_deref()) {
+            Ok(generated) => tests.extend(generated),
+            Err(e) => {
+                return Err(GradeError::Failed(e));
+            }
+        }
+    }
+    if question.settings.reference.is_some() {
+        match references.expected_outputs(jail, question, &tests, profile.as_deref()) {
+            Ok(outputs) => {
+                for (case, output) in tests.iter_mut().zip(outputs) {
+                    if let Some(output) = output {
+                        case.expected_output = output;
+                    }
+                }
+            }
+            Err(e) => {
+                return Err(GradeError::Failed(e));
+            }
+        }
+    }
+
+    let tmp_path = work_dir.join("main.c");
+
+    // Save the submitted C file to disk.
+    if let Err(e) = fs::write(&tmp_path, &attempt.source) {
+        return Err(GradeError::Failed(format!("Error saving file: {}", e)));
+    }
+
+    let source = String::from_utf8_lossy(&attempt.source).into_owned();
+    let seed = question.settings.generator.as_ref().map(|_| seed);
+
+    // Compile the C file using gcc inside the jail.
+    let compile_limits = question.compile_limits();
+    let compiled = compiler::compile(jail, &work_dir, &compile_limits, profile.as_deref(), &sandbox_log);
+    let compiled = match compiled {
+        Ok(compiled) => compiled,
+        Err(e) => {
+                let compiler_output = e.to_string();
+            record(store, &store::Submission {
+                id: unique_id,
+                student,
+                question: selected_question,
+                source: &source,
+                compiled: false,
+                compiler_output: &compiler_output,
+                seed,
+                tests: &[],
+                results: &[],
+                score: None,
+            });
+            return Err(GradeError::Compile(e));
         }
     };
+    let exe_path = compiled.executable;
+    let exe_path_str = exe_path.to_string_lossy().into_owned();
 
+    let seccomp_policy = seccomp::policy(&question.settings.allowed_syscalls);
     let mut results = Vec::new();
 
     // Loop through each test case.
-    for case in cases {
+    for case in &tests {
         // Check again that the executable exists.
         if !Path::new(&exe_path).exists() {
             eprintln!("Executable not found at: {}", exe_path_str);
-            return RawHtml("<h2>Internal error: compiled executable not found.</h2>".to_string());
+            return Err(GradeError::Failed("Internal error: compiled executable not found.".to_string()));
         }
 
-        // Use NSJail to run the executable.
-        let mut child = match Command::new("nsjail")
-            .args(&[
-                "--mode=exec",
-                "--disable_clone_newuser",
-                "--bindmount", "/app/tempfiles:/app/tempfiles",
-                "--bindmount", "/lib:/lib",
-                "--bindmount", "/usr/lib:/usr/lib",
-                "--", &exe_path_str,
-            ])
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn() {
-                Ok(child) => child,
-                Err(e) => {
-                    results.push((case.description.clone(), false, format!("Error running the program with NSJail: {}", e)));
-                    continue;
-                }
-            };
-
-        // Write the test case input to the program's stdin.
-        {
-            let child_stdin = child.stdin.as_mut().expect("Failed to open stdin");
-            if let Err(e) = child_stdin.write_all(case.input.as_bytes()) {
-                results.push((case.description.clone(), false, format!("Error writing to stdin: {}", e)));
-                continue;
-            }
+        // Each test runs in a fresh scratch directory holding its input files;
+        // the executable itself is read-only.
+        let run_dir = submission_dir.join("run");
+        if let Err(e) = case.write_files(&run_dir) {
+            results.push(TestResult::error(&case.description, format!("Error preparing the test's files: {}", e)));
+            continue;
         }
-
-        // Wait for the program to finish and capture its output.
-        let run_output = match child.wait_with_output() {
-            Ok(output) => output,
+        let limits = question.limits(case);
+        let mut run_mounts = sandbox::runtime_mounts(&run_dir);
+        run_mounts.push(sandbox::Mount::read_only(&work_dir));
+        let env = case.env_vars();
+        let job = sandbox::Job {
+            program: &exe_path,
+            args: &case.args,
+            mounts: &run_mounts,
+            cwd: Some(&run_dir),
+            env: &env,
+            limits: &limits,
+            profile: profile.as_deref(),
+            seccomp_policy: Some(&seccomp_policy),
+            log_file: Some(&sandbox_log),
+        };
+        // Run the program, feeding the test case input to its stdin or, for
+        // interactive tests, playing the scripted conversation.
+        let ran = if case.interaction.is_empty() {
+            jail.run(&job, case.input.as_bytes()).map(|outcome| (outcome, None))
+        } else {
+            jail.run_interactive(&job, &case.interaction).map(|(outcome, transcript)| (outcome, Some(transcript)))
+        };
+        let (run_output, transcript) = match ran {
+            Ok(ran) => ran,
             Err(e) => {
-                results.push((case.description.clone(), false, format!("Error waiting for output: {}", e)));
+                results.push(TestResult::error(&case.description, format!("Error running the program with {}: {}", jail.name(), e)));
                 continue;
             }
         };
 
-        let actual_output = String::from_utf8_lossy(&run_output.stdout).trim().to_string();
-        let expected_substring = case.expected_output.trim();
-        let passed = actual_output.contains(expected_substring);
-        let result_text = format!("Input: {}\nExpected to contain: {}\nGot: {}", case.input, expected_substring, actual_output);
-        results.push((case.description.clone(), passed, result_text));
+        let checked = case.checker.as_ref().map(|checker| {
+            checker::run(
+                jail,
+                Path::new(checker),
+                &submission_dir,
+                profile.as_deref(),
+                &case.input,
+                &case.expected_output,
+                &run_output.stdout,
+            )
+        });
+        results.push(grading::judge(case, &limits, &run_output, jail, checked.as_ref(), &run_dir, transcript));
     }
 
-    // Clean up temporary files after processing all test cases.
-    let _ = fs::remove_file(&tmp_path);
-    let _ = fs::remove_file(&exe_path);
+    let score = scoring::score(question, &tests, &results);
+    record(store, &store::Submission {
+        id: unique_id,
+        student,
+        question: selected_question,
+        source: &source,
+        compiled: true,
+        compiler_output: &compiled.diagnostics,
+        seed,
+        tests: &tests,
+        results: &results,
+        score: Some(&score),
+    });
+    Ok(GradedSubmission { id: unique_id.to_string(), tests, results, score, seed })
+}
+
+/// Records `submission`. A database error is logged rather than shown, so that
+/// the student still gets their results.
+fn record(store: &Store, submission: &store::Submission) {
+    if let Err(e) = store.record(submission) {
+        eprintln!("Error recording submission {}: {}", submission.id, e);
+    }
+}
 
+/// POST /upload
+/// Handles the file upload and grades it as the logged-in student's submission.
+/// Displays a test summary along with the results of the tests students may see.
+#[post("/upload", data = "<form>")]
+async fn upload(
+    form: CsrfForm<Upload<'_>>,
+    student: User,
+    jail: &State<Arc<dyn Sandbox>>,
+    profiles: &State<Arc<Profiles>>,
+    references: &State<Arc<References>>,
+    store: &State<Arc<Store>>,
+) -> RawHtml<String> {
+    if form.seed.is_some() {
+        return RawHtml(GradeError::Failed(SEED_FORBIDDEN.to_string()).html());
+    }
+    let id = uuid::Uuid::new_v4().to_string();
+    let graded = match read_upload(&form).await {
+        Ok(source) => {
+            let attempt = Attempt { question: form.question.clone(), source, seed: None };
+            grade(attempt, &id, Some(&student.id), jail, profiles, references, store).await
+        }
+        Err(e) => Err(e),
+    };
+    match graded {
+        Ok(submission) => RawHtml(results_page(&submission, false, "")),
+        Err(e) => RawHtml(e.html()),
+    }
+}
+
+/// GET /submissions/<id>, for TAs and instructors.
+/// Shows any recorded submission in full, with a form for leaving feedback.
+#[get("/submissions/<id>")]
+async fn submission_page_staff(
+    id: &str,
+    staff: Staff,
+    cookies: &CookieJar<'_>,
+    store: &State<Arc<Store>>,
+) -> Option<RawHtml<String>> {
+    stored_submission_page(store, cookies, id, Some(&staff), None)
+}
+
+/// GET /submissions/<id>
+/// Shows one of the student's own recorded submissions again, as students see it.
+#[get("/submissions/<id>", rank = 2)]
+async fn submission_page(
+    id: &str,
+    student: User,
+    cookies: &CookieJar<'_>,
+    store: &State<Arc<Store>>,
+) -> Option<RawHtml<String>> {
+    stored_submission_page(store, cookies, id, None, Some(&student))
+}
+
+/// The page of the recorded submission `id` with the feedback left on it, if
+/// it exists and is either seen by `staff` or belongs to `student`.
+fn stored_submission_page(
+    store: &Store,
+    cookies: &CookieJar<'_>,
+    id: &str,
+    staff: Option<&Staff>,
+    student: Option<&User>,
+) -> Option<RawHtml<String>> {
+    let stored = match store.load(id) {
+        Ok(Some(stored)) => stored,
+        Ok(None) => return None,
+        Err(e) => return Some(RawHtml(format!("<h2>Error reading submission: {}</h2>", e))),
+    };
+    // Other students' submissions are not found rather than forbidden, so their IDs reveal nothing.
+    if staff.is_none() && stored.summary.student.as_deref() != student.map(|student| student.id.as_str()) {
+        return None;
+    }
+    let feedback = store.feedback(id).unwrap_or_else(|e| {
+        eprintln!("Error reading feedback on {}: {}", id, e);
+        Vec::new()
+    });
+    let mut feedback_html = String::new();
+    if staff.is_some() {
+        feedback_html.push_str(&format!(
+            "<p class='mt-3'>Submitted {} UTC by {} for question {}.</p>",
+            stored.summary.submitted_at,
+            htmlescape::encode_minimal(stored.summary.student.as_deref().unwrap_or("an instructor")),
+            htmlescape::encode_minimal(&stored.summary.question)
+        ));
+    }
+    if !feedback.is_empty() || staff.is_some() {
+        feedback_html.push_str("<h2 class='mt-3'>Feedback</h2>");
+    }
+    for comment in &feedback {
+        feedback_html.push_str(&format!(
+            "<div class='card mb-2'><div class='card-body'><p class='card-text' style='white-space: pre-wrap;'>{}</p><small class='text-muted'>{}, {} UTC</small></div></div>",
+            htmlescape::encode_minimal(&comment.body),
+            htmlescape::encode_minimal(&comment.author),
+            comment.created_at
+        ));
+    }
+    if staff.is_some() {
+        feedback_html.push_str(&format!(
+            "<form action='/submissions/{}/feedback' method='post'>
+               {}
+               <div class='mb-3'><textarea name='body' class='form-control' rows='4' placeholder='Feedback for the student'></textarea></div>
+               <button type='submit' class='btn btn-primary'>Add Feedback</button>
+             </form>",
+            htmlescape::encode_attribute(id),
+            csrf::field(cookies)
+        ));
+    }
+
+    if !stored.summary.compiled {
+        let error = compile_error_html(&CompileError::Errors(stored.compiler_output.clone()));
+        return Some(RawHtml(format!(
+            "{}<p>Submitted {} UTC for question {}.</p>{}<a href='/submissions'>Your submissions</a>",
+            error,
+            stored.summary.submitted_at,
+            htmlescape::encode_minimal(&stored.summary.question),
+            feedback_html
+        )));
+    }
+    Some(RawHtml(results_page(&stored.into(), staff.is_some(), &feedback_html)))
+}
+
+/// Form data for leaving feedback on a submission.
+#[derive(FromForm)]
+struct FeedbackForm {
+    body: String,
+    #[field(default = String::new())]
+    csrf_token: String,
+}
+
+impl CsrfProtected for FeedbackForm {
+    fn csrf_token(&self) -> &str {
+        &self.csrf_token
+    }
+}
+
+/// POST /submissions/<id>/feedback
+/// Adds a TA's or instructor's feedback to a submission.
+#[post("/submissions/<id>/feedback", data = "<form>")]
+async fn add_feedback(id: &str, form: CsrfForm<FeedbackForm>, staff: Staff, store: &State<Arc<Store>>) -> Result<Redirect, RawHtml<String>> {
+    let body = form.body.trim();
+    if body.is_empty() {
+        return Err(RawHtml(format!("<h2>Feedback cannot be empty.</h2><a href='/submissions/{}'>Back</a>", htmlescape::encode_attribute(id))));
+    }
+    match store.add_feedback(id, &staff.name, body) {
+        Ok(()) => Ok(Redirect::to(uri!(submission_page(id)))),
+        Err(e) => Err(RawHtml(format!("<h2>Error saving feedback: {}</h2>", e))),
+    }
+}
+
+/// GET /submissions
+/// Lists the logged-in student's submissions, newest first, with links to their results.
+#[get("/submissions")]
+async fn submissions_page(student: User, store: &State<Arc<Store>>) -> RawHtml<String> {
+    let summaries = match store.submissions_of(&student.id) {
+        Ok(summaries) => summaries,
+        Err(e) => return RawHtml(format!("<h2>Error reading submissions: {}</h2>", e)),
+    };
+    let rows: String = summaries.iter().map(|summary| {
+        let result = match (summary.compiled, summary.grade, summary.max_grade) {
+            (true, Some(grade), Some(max_grade)) => format!("{:.2} / {:.2}", grade, max_grade),
+            _ => "Did not compile".to_string(),
+        };
+        format!(
+            "<tr><td>{} UTC</td><td>{}</td><td>{}</td><td><a href='/submissions/{}'>Results</a></td></tr>",
+            summary.submitted_at,
+            htmlescape::encode_minimal(&summary.question),
+            result,
+            summary.id
+        )
+    }).collect();
+    let table = if summaries.is_empty() {
+        "<p>No submissions yet.</p>".to_string()
+    } else {
+        format!(
+            "<table class='table'><thead><tr><th>Submitted</th><th>Question</th><th>Score</th><th></th></tr></thead><tbody>{}</tbody></table>",
+            rows
+        )
+    };
+    RawHtml(format!(r#"
+    <!DOCTYPE html>
+    <html>
+      <head>
+        <meta charset="UTF-8">
+        <title>Your Submissions - Autograder</title>
+        <link href="https://cdn.jsdelivr.net/npm/bootstrap@5.3.0/dist/css/bootstrap.min.css" rel="stylesheet">
+        <style>
+          body {{ background-color: #f8f9fa; }}
+        </style>
+      </head>
+      <body>
+        <div class="container mt-5">
+          <h1>Your Submissions</h1>
+          {}
+          <a href="/" class="btn btn-secondary mt-3">Upload another file</a>
+        </div>
+      </body>
+    </html>
+    "#, table))
+}
+
+/// The results page for `submission`. Students see each test according to its
+/// visibility; instructors see every test in full. `footer_html` goes after the results.
+fn results_page(submission: &GradedSubmission, instructor: bool, footer_html: &str) -> String {
+    let results = &submission.results;
     // Calculate test summary.
 let total_tests = results.len();
-let passed_tests = results.iter().filter(|(_, passed, _)| *passed).count();
+let passed_tests = results.iter().filter(|r| r.verdict.passed()).count();
 let passing_percentage = if total_tests > 0 {
     passed_tests as f64 / total_tests as f64 * 100.0
 } else {
     0.0
 };
+let seed_html = match submission.seed {
+    Some(seed) if instructor => format!("<p>Random inputs were generated with seed {}. Run the submission again with this seed to get the same inputs.</p>", seed),
+    Some(seed) => format!("<p>Random inputs were generated with seed {}.</p>", seed),
+    None => String::new(),
+};
+let score = &submission.score;
+let groups_html = if score.groups.len() > 1 {
+    let rows: String = score.groups.iter().map(|group| {
+        format!(
+            "<tr><td>{}</td><td>{:.2} / {:.2}</td></tr>",
+            htmlescape::encode_minimal(&group.name),
+            group.earned,
+            group.possible
+        )
+    }).collect();
+    format!("<table class='table table-sm w-auto'><tbody>{}</tbody></table>", rows)
+} else {
+    String::new()
+};
 let summary_html = format!(
     "<div class='alert alert-info' style='font-family: \"Segoe UI\", sans-serif;'>
        <h2>Test Summary</h2>
        <p>Passed {}/{} test cases ({:.2}%)</p>
+       <p><strong>Score: {:.2} / {:.2}</strong></p>
+       {}
+       {}
+       <p class='text-muted'><small>Permanent link to these results: <a href='/submissions/{id}'>/submissions/{id}</a></small></p>
      </div>",
-    passed_tests, total_tests, passing_percentage
+    passed_tests, total_tests, passing_percentage, score.grade, score.max_grade, groups_html, seed_html, id = submission.id
 );
 
 // Build the HTML output with improved styling.
@@ -314,27 +770,91 @@ let mut results_html = String::from("<h1 style='font-family: \"Segoe UI\", sans-
 results_html.push_str(&summary_html);
 results_html.push_str("<div id='results'>");
 
-for (i, (desc, passed, details)) in results.into_iter().enumerate() {
+let mut hidden_total = 0;
+let mut hidden_passed = 0;
+let mut shown = 0;
+for (case, result) in submission.tests.iter().zip(results) {
+    let passed = result.verdict.passed();
     let bg_class = if passed { "bg-success" } else { "bg-danger" };
+    if !instructor && case.visibility == Visibility::Hidden {
+        hidden_total += 1;
+        hidden_passed += usize::from(passed);
+        continue;
+    }
+    let i = shown;
+    shown += 1;
+    let description = match (instructor, case.visibility) {
+        (true, Visibility::Hidden) => format!("{} (hidden)", result.description),
+        (true, Visibility::NameOnly) => format!("{} (name only)", result.description),
+        _ => result.description.clone(),
+    };
+    if !instructor && case.visibility == Visibility::NameOnly {
+        results_html.push_str(&format!(
+            "<div class='list-group-item {} text-white test-result' style='display:none; font-family: \"Segoe UI\", sans-serif; padding: 10px; border-radius: 5px; margin-bottom: 5px;' data-delay='{}'>
+           <strong>{}</strong>: {}
+         </div>",
+            bg_class,
+            i * 500,
+            htmlescape::encode_minimal(&description),
+            htmlescape::encode_minimal(&result.verdict.to_string())
+        ));
+        continue;
+    }
     // For failed tests, wrap the details in a diff span to highlight the error.
+    // They include the program's output, so they are escaped like everything else it wrote.
+    let details = htmlescape::encode_minimal(&result.details);
     let detail_markup = if passed {
         details
     } else {
         format!("<span class='diff'>{}</span>", details)
     };
+    let stderr_markup = if result.stderr.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "<details><summary>Standard error ({} bytes)</summary><pre style='background-color: #f1f1f1; color: #333; padding: 10px; border-radius: 5px; font-family: \"Courier New\", monospace;'>{}</pre></details>",
+            result.stderr.len(),
+            htmlescape::encode_minimal(&result.stderr)
+        )
+    };
+    let files_markup: String = result.file_diffs.iter().map(|file| {
+        format!(
+            "<details><summary>Output file {}</summary><pre style='background-color: #f1f1f1; color: #333; padding: 10px; border-radius: 5px; font-family: \"Courier New\", monospace;'>{}</pre></details>",
+            htmlescape::encode_minimal(&file.name),
+            htmlescape::encode_minimal(&file.diff)
+        )
+    }).collect();
+    let transcript_markup = result.transcript.as_ref().map(transcript_html).unwrap_or_default();
     results_html.push_str(&format!(
         "<div class='list-group-item {} text-white test-result' style='display:none; font-family: \"Segoe UI\", sans-serif; padding: 10px; border-radius: 5px; margin-bottom: 5px;' data-delay='{}'>
-           <strong>{}</strong>: {}
+           <strong>{}</strong>: {} <small class='float-end'>{}</small>
            <pre style='background-color: #f1f1f1; color: #333; padding: 10px; border-radius: 5px; font-family: \"Courier New\", monospace;'>{}</pre>
+           {}
+           {}
+           {}
          </div>",
         bg_class,
         i * 500,
-        desc,
-        if passed { "Passed" } else { "Failed" },
-        detail_markup
+        htmlescape::encode_minimal(&description),
+        htmlescape::encode_minimal(&result.verdict.to_string()),
+        result.usage_label(),
+        detail_markup,
+        transcript_markup,
+        files_markup,
+        stderr_markup
+    ));
+}
+if hidden_total > 0 {
+    results_html.push_str(&format!(
+        "<div class='list-group-item bg-secondary text-white test-result' style='display:none; font-family: \"Segoe UI\", sans-serif; padding: 10px; border-radius: 5px; margin-bottom: 5px;'>
+           <strong>Hidden tests</strong>: passed {} of {}
+         </div>",
+        hidden_passed, hidden_total
     ));
 }
-results_html.push_str("</div><a href='/' class='btn btn-secondary mt-3' style='font-family: \"Segoe UI\", sans-serif;'>Upload another file</a>");
+results_html.push_str("</div>");
+results_html.push_str(footer_html);
+results_html.push_str("<a href='/' class='btn btn-secondary mt-3' style='font-family: \"Segoe UI\", sans-serif;'>Upload another file</a> <a href='/submissions' class='btn btn-link mt-3'>Your submissions</a>");
 
 let script = r#"
 <script>
@@ -397,17 +917,130 @@ let full_html = format!(r#"
 </html>
 "#, results_html, script);
 
-RawHtml(full_html)
+full_html
+}
+
+//
+// Student Login Routes
+//
+
+/// GET /login
+/// Returns the student login page.
+#[get("/login")]
+async fn login_page(cookies: &CookieJar<'_>) -> RawHtml<String> {
+    RawHtml(r#"
+    <!DOCTYPE html>
+    <html>
+      <head>
+        <meta charset="UTF-8">
+        <title>Login - Autograder</title>
+        <link href="https://cdn.jsdelivr.net/npm/bootstrap@5.3.0/dist/css/bootstrap.min.css" rel="stylesheet">
+        <style>
+          body { background-color: #f8f9fa; }
+          .header { text-align: center; margin-top: 20px; }
+        </style>
+      </head>
+      <body>
+        <div class="container mt-5">
+          <div class="header">
+            <h1>Autograder</h1>
+            <p>Log in with your student ID and the token your instructor gave you.</p>
+          </div>
+          <form action="/login" method="post">
+            <!-- csrf -->
+            <div class="mb-3">
+              <label for="id" class="form-label">Student ID:</label>
+              <input type="text" id="id" name="id" class="form-control">
+            </div>
+            <div class="mb-3">
+              <label for="token" class="form-label">Token:</label>
+              <input type="password" id="token" name="token" class="form-control">
+            </div>
+            <button type="submit" class="btn btn-primary">Login</button>
+          </form>
+        </div>
+      </body>
+    </html>
+    "#.replace("<!-- csrf -->", &csrf::field(cookies)))
+}
+
+/// Data structure representing student login credentials.
+#[derive(FromForm)]
+struct StudentLogin {
+    id: String,
+    token: String,
+    #[field(default = String::new())]
+    csrf_token: String,
+}
+
+impl CsrfProtected for StudentLogin {
+    fn csrf_token(&self) -> &str {
+        &self.csrf_token
+    }
+}
+
+/// POST /login
+/// Checks the student's token and, if it is right, starts a session.
+#[post("/login", data = "<form>")]
+async fn login(form: CsrfForm<StudentLogin>, cookies: &CookieJar<'_>, store: &State<Arc<Store>>) -> Result<Redirect, RawHtml<String>> {
+    let id = form.id.trim();
+    let student = match store.student(id) {
+        Ok(student) => student,
+        Err(e) => return Err(RawHtml(format!("<h2>Error reading student: {}</h2>", e))),
+    };
+    let verified = match &student {
+        Some(student) => {
+            let (token, token_hash) = (form.token.trim().to_string(), student.token_hash.clone());
+            rocket::tokio::task::spawn_blocking(move || auth::verify(&token, &token_hash)).await.unwrap_or(false)
+        }
+        None => false,
+    };
+    match student {
+        Some(student) if verified => {
+            auth::log_in_student(cookies, &student.id);
+            Ok(Redirect::to(uri!(index)))
+        }
+        _ => Err(RawHtml("<h2>Unknown student ID or wrong token.</h2><a href='/login'>Try again</a>".to_string())),
+    }
+}
+
+/// GET /logout
+/// Ends the student's session.
+#[get("/logout")]
+async fn logout(cookies: &CookieJar<'_>) -> Redirect {
+    auth::log_out_student(cookies);
+    Redirect::to(uri!(login_page))
+}
+
+/// Shown for pages that need a logged-in student or admin when there is none.
+#[catch(401)]
+fn unauthorized(request: &rocket::Request) -> RawHtml<&'static str> {
+    if request.uri().path().starts_with("/admin") {
+        RawHtml("<h2>Please log in as an admin first.</h2><a href='/admin'>Log in</a>")
+    } else {
+        RawHtml("<h2>Please log in first.</h2><a href='/login'>Log in</a>")
+    }
+}
+
+/// Shown for pages the logged-in student or TA's role does not allow, and for
+/// forms without a valid CSRF token.
+#[catch(403)]
+fn forbidden(request: &rocket::Request) -> RawHtml<&'static str> {
+    if request.local_cache(|| CsrfRejected(false)).0 {
+        RawHtml("<h2>This form has expired or was sent from another site.</h2><p>Go back, reload the page and try again.</p>")
+    } else {
+        RawHtml("<h2>Your role does not allow this.</h2><a href='/'>Back to the autograder</a>")
+    }
 }
 
 //
 // Admin Panel Routes
 //
 
-/// GET /admin
+/// GET /admin, without an admin session.
 /// Returns the admin login page with enhanced styling and autograder info.
-#[get("/admin")]
-async fn admin_login_page() -> RawHtml<String> {
+#[get("/admin", rank = 2)]
+async fn admin_login_page(cookies: &CookieJar<'_>) -> RawHtml<String> {
     RawHtml(r#"
     <!DOCTYPE html>
     <html>
@@ -427,6 +1060,11 @@ async fn admin_login_page() -> RawHtml<String> {
             <p>Please login to manage test cases.</p>
           </div>
           <form action="/admin" method="post">
+            <!-- csrf -->
+            <div class="mb-3">
+              <label for="username" class="form-label">Username:</label>
+              <input type="text" id="username" name="username" class="form-control">
+            </div>
             <div class="mb-3">
               <label for="password" class="form-label">Password:</label>
               <input type="password" id="password" name="password" class="form-control">
@@ -436,23 +1074,54 @@ async fn admin_login_page() -> RawHtml<String> {
         </div>
       </body>
     </html>
-    "#.to_string())
+    "#.replace("<!-- csrf -->", &csrf::field(cookies)))
 }
 
 /// Data structure representing admin login credentials.
 #[derive(rocket::form::FromForm)]
 struct AdminLogin {
+    username: String,
     password: String,
+    #[field(default = String::new())]
+    csrf_token: String,
+}
+
+impl CsrfProtected for AdminLogin {
+    fn csrf_token(&self) -> &str {
+        &self.csrf_token
+    }
 }
 
 /// POST /admin
-/// Processes the admin login and, if successful, displays the admin panel.
+/// Processes the admin login and, if successful, starts a session and goes to the admin panel.
 #[post("/admin", data = "<form>")]
-async fn admin_login(form: Form<AdminLogin>) -> RawHtml<String> {
-    let admin_password = "secret";
-    if form.password != admin_password {
-        return RawHtml("<h2>Invalid password.</h2><a href='/admin'>Try again</a>".to_string());
+async fn admin_login(
+    form: CsrfForm<AdminLogin>,
+    cookies: &CookieJar<'_>,
+    config: &State<GraderConfig>,
+) -> Result<Redirect, RawHtml<String>> {
+    if !auth::check_admin(config, &form.username, &form.password).await {
+        return Err(RawHtml("<h2>Invalid username or password.</h2><a href='/admin'>Try again</a>".to_string()));
     }
+    auth::log_in_admin(cookies, config, &form.username);
+    Ok(Redirect::to(uri!(admin_panel)))
+}
+
+/// GET /admin/logout
+/// Ends the admin's or staff member's session.
+#[get("/admin/logout")]
+async fn admin_logout(cookies: &CookieJar<'_>) -> Redirect {
+    // Staff on the roster reach the panel through their student session.
+    auth::log_out_admin(cookies);
+    auth::log_out_student(cookies);
+    Redirect::to(uri!(admin_login_page))
+}
+
+/// GET /admin
+/// Displays the admin panel to a TA or instructor. Only instructors get the
+/// links for changing questions, tests and the roster.
+#[get("/admin")]
+async fn admin_panel(staff: Staff) -> RawHtml<String> {
     let html = r#"
        <!DOCTYPE html>
        <html>
@@ -467,10 +1136,18 @@ async fn admin_login(form: Form<AdminLogin>) -> RawHtml<String> {
          </head>
          <body>
            <div class="container mt-5">
+             <p class="text-end">Logged in as <!-- username --> &middot; <a href="/admin/logout">Log out</a></p>
              <div class="header">
                <h1>Autograder Admin Panel</h1>
-               <p>Select a question to edit its test cases.</p>
              </div>
+             <a href="/admin/submissions" class="btn btn-primary mb-3">Browse All Submissions</a>
+             <!-- instructor -->
+           </div>
+         </body>
+       </html>
+    "#;
+    let instructor_html = r#"
+            <p>Select a question to edit its test cases.</p>
             <ul class="list-group">
               <li class="list-group-item"><a href="/admin/edit?question=q1">Edit Q1 Test Cases</a></li>
               <li class="list-group-item"><a href="/admin/edit?question=q2">Edit Q2 Test Cases</a></li>
@@ -494,21 +1171,433 @@ async fn admin_login(form: Form<AdminLogin>) -> RawHtml<String> {
               <li class="list-group-item"><a href="/admin/edit?question=q20">Edit Q20 Test Cases</a></li>
               <li class="list-group-item"><a href="/admin/edit?question=q21">Edit Q21 Test Cases</a></li>
             </ul>
-           </div>
-         </body>
-       </html>
+            <a href="/admin/run" class="btn btn-primary mt-3">Run a Submission with Full Results</a>
+            <a href="/admin/roster" class="btn btn-primary mt-3">Manage the Class Roster</a>
+            <a href="/admin/roles" class="btn btn-primary mt-3">Manage Roles</a>
     "#;
-    RawHtml(html.to_string())
+    let html = html.replace("<!-- username -->", &format!("{} ({})", htmlescape::encode_minimal(&staff.name), staff.role));
+    if staff.role == Role::Instructor {
+        RawHtml(html.replace("<!-- instructor -->", instructor_html))
+    } else {
+        RawHtml(html)
+    }
+}
+
+/// GET /admin/run
+/// Returns a form for grading a submission with every test shown in full,
+/// including hidden ones.
+#[get("/admin/run")]
+async fn admin_run_page(_instructor: Instructor, cookies: &CookieJar<'_>) -> RawHtml<String> {
+    RawHtml(r#"
+    <!DOCTYPE html>
+    <html>
+      <head>
+        <meta charset="UTF-8">
+        <title>Instructor Run - Autograder</title>
+        <link href="https://cdn.jsdelivr.net/npm/bootstrap@5.3.0/dist/css/bootstrap.min.css" rel="stylesheet">
+        <style>
+          body { background-color: #f8f9fa; }
+        </style>
+      </head>
+      <body>
+        <div class="container mt-5">
+          <h1>Run a Submission</h1>
+          <p>Grades a C file and shows every test in full, including hidden ones.</p>
+          <form action="/admin/run" method="post" enctype="multipart/form-data">
+            <!-- csrf -->
+            <div class="mb-3">
+              <label for="question" class="form-label">Question:</label>
+              <input type="text" id="question" name="question" class="form-control" value="q1">
+            </div>
+            <div class="mb-3">
+              <label for="file" class="form-label">C File:</label>
+              <input type="file" class="form-control" id="file" name="file" accept=".c">
+            </div>
+            <div class="mb-3">
+              <label for="seed" class="form-label">Random seed (optional):</label>
+              <input type="number" class="form-control" id="seed" name="seed" min="0">
+            </div>
+            <button type="submit" class="btn btn-primary">Run</button>
+          </form>
+          <a href="/admin" class="btn btn-secondary mt-3">Back to Admin Panel</a>
+        </div>
+      </body>
+    </html>
+    "#.replace("<!-- csrf -->", &csrf::field(cookies)))
+}
+
+/// POST /admin/run
+/// Grades a submission like /upload, but shows the instructor view of the results.
+#[post("/admin/run", data = "<form>")]
+async fn admin_run(
+    form: CsrfForm<Upload<'_>>,
+    _instructor: Instructor,
+    jail: &State<Arc<dyn Sandbox>>,
+    profiles: &State<Arc<Profiles>>,
+    references: &State<Arc<References>>,
+    store: &State<Arc<Store>>,
+) -> RawHtml<String> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let graded = match read_upload(&form).await {
+        Ok(source) => {
+            let attempt = Attempt { question: form.question.clone(), source, seed: form.seed };
+            grade(attempt, &id, None, jail, profiles, references, store).await
+        }
+        Err(e) => Err(e),
+    };
+    match graded {
+        Ok(submission) => RawHtml(results_page(&submission, true, "")),
+        Err(e) => RawHtml(e.html()),
+    }
+}
+
+/// GET /admin/roster
+/// Lists the students on the roster, with a form for importing a roster CSV.
+#[get("/admin/roster")]
+async fn admin_roster_page(_instructor: Instructor, cookies: &CookieJar<'_>, store: &State<Arc<Store>>) -> RawHtml<String> {
+    let csrf_field = csrf::field(cookies);
+    let students = match store.students() {
+        Ok(students) => students,
+        Err(e) => return RawHtml(format!("<h2>Error reading the roster: {}</h2>", e)),
+    };
+    let rows: String = students.iter().map(|student| {
+        format!(
+            "<tr><td>{id}</td><td>{}</td><td><form action='/admin/roster/reset' method='post' class='m-0'>{}<input type='hidden' name='id' value='{id}'><button type='submit' class='btn btn-sm btn-outline-secondary'>New token</button></form></td></tr>",
+            htmlescape::encode_minimal(&student.name),
+            csrf_field,
+            id = htmlescape::encode_attribute(&student.id)
+        )
+    }).collect();
+    RawHtml(format!(r#"
+    <!DOCTYPE html>
+    <html>
+      <head>
+        <meta charset="UTF-8">
+        <title>Class Roster - Autograder</title>
+        <link href="https://cdn.jsdelivr.net/npm/bootstrap@5.3.0/dist/css/bootstrap.min.css" rel="stylesheet">
+        <style>
+          body {{ background-color: #f8f9fa; }}
+        </style>
+      </head>
+      <body>
+        <div class="container mt-5">
+          <h1>Class Roster</h1>
+          <form action="/admin/roster" method="post" enctype="multipart/form-data">
+            {}
+            <div class="mb-3">
+              <label for="file" class="form-label">Roster CSV, with a header row naming at least the <code>id</code> and <code>name</code> columns:</label>
+              <input type="file" class="form-control" id="file" name="file" accept=".csv">
+            </div>
+            <button type="submit" class="btn btn-primary">Import</button>
+          </form>
+          <p class="mt-3">Students who are new get a login token. Students already on the roster keep theirs.</p>
+          <table class="table">
+            <thead><tr><th>ID</th><th>Name</th><th></th></tr></thead>
+            <tbody>{}</tbody>
+          </table>
+          <a href="/admin" class="btn btn-secondary mt-3">Back to Admin Panel</a>
+        </div>
+      </body>
+    </html>
+    "#, csrf_field, rows))
+}
+
+/// Form data for importing a roster.
+#[derive(FromForm)]
+struct RosterUpload<'r> {
+    file: TempFile<'r>,
+    #[field(default = String::new())]
+    csrf_token: String,
+}
+
+impl CsrfProtected for RosterUpload<'_> {
+    fn csrf_token(&self) -> &str {
+        &self.csrf_token
+    }
+}
+
+/// POST /admin/roster
+/// Imports a roster CSV and shows the login tokens of the students it added.
+#[post("/admin/roster", data = "<form>")]
+async fn admin_roster_import(form: CsrfForm<RosterUpload<'_>>, _instructor: Instructor, store: &State<Arc<Store>>) -> RawHtml<String> {
+    use rocket::tokio::io::AsyncReadExt;
+
+    let mut csv_text = String::new();
+    let read = match form.file.open().await {
+        Ok(mut file) => file.read_to_string(&mut csv_text).await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = read {
+        return RawHtml(format!("<h2>Error reading the roster: {}</h2><a href='/admin/roster'>Back</a>", e));
+    }
+    // Hashing the new tokens takes a while for a whole class.
+    let store = Arc::clone(store);
+    let imported = rocket::tokio::task::spawn_blocking(move || {
+        roster::parse(&csv_text).and_then(|entries| roster::import(&store, entries))
+    })
+    .await
+    .unwrap_or_else(|e| Err(format!("Importing stopped unexpectedly: {}", e)));
+    let imported = match imported {
+        Ok(imported) => imported,
+        Err(e) => {
+            return RawHtml(format!(
+                "<h2>The roster was not imported.</h2><p>{}</p><a href='/admin/roster'>Back</a>",
+                htmlescape::encode_minimal(&e)
+            ))
+        }
+    };
+    let added = imported.iter().filter(|student| student.token.is_some()).count();
+    let rows: String = imported.iter().map(|student| {
+        format!(
+            "<tr><td>{}</td><td>{}</td><td><code>{}</code></td></tr>",
+            htmlescape::encode_minimal(&student.entry.id),
+            htmlescape::encode_minimal(&student.entry.name),
+            student.token.as_deref().unwrap_or("(unchanged)")
+        )
+    }).collect();
+    RawHtml(format!(r#"
+    <!DOCTYPE html>
+    <html>
+      <head>
+        <meta charset="UTF-8">
+        <title>Roster Imported - Autograder</title>
+        <link href="https://cdn.jsdelivr.net/npm/bootstrap@5.3.0/dist/css/bootstrap.min.css" rel="stylesheet">
+        <style>
+          body {{ background-color: #f8f9fa; }}
+        </style>
+      </head>
+      <body>
+        <div class="container mt-5">
+          <h1>Roster Imported</h1>
+          <div class="alert alert-warning">Imported {} students, {} of them new. Give each new student their token now: tokens are stored hashed and cannot be shown again.</div>
+          <table class="table">
+            <thead><tr><th>ID</th><th>Name</th><th>Token</th></tr></thead>
+            <tbody>{}</tbody>
+          </table>
+          <a href="/admin/roster" class="btn btn-secondary mt-3">Back to the Roster</a>
+        </div>
+      </body>
+    </html>
+    "#, imported.len(), added, rows))
+}
+
+/// Form data naming a student on the roster.
+#[derive(FromForm)]
+struct RosterStudent {
+    id: String,
+    #[field(default = String::new())]
+    csrf_token: String,
+}
+
+impl CsrfProtected for RosterStudent {
+    fn csrf_token(&self) -> &str {
+        &self.csrf_token
+    }
+}
+
+/// POST /admin/roster/reset
+/// Gives a student a new login token, for when they have lost theirs.
+#[post("/admin/roster/reset", data = "<form>")]
+async fn admin_roster_reset(form: CsrfForm<RosterStudent>, _instructor: Instructor, store: &State<Arc<Store>>) -> RawHtml<String> {
+    let student = match store.student(&form.id) {
+        Ok(Some(student)) => student,
+        Ok(None) => return RawHtml("<h2>No such student.</h2><a href='/admin/roster'>Back</a>".to_string()),
+        Err(e) => return RawHtml(format!("<h2>Error reading student: {}</h2>", e)),
+    };
+    let token = auth::new_token();
+    let hashed_token = token.clone();
+    let hashed = rocket::tokio::task::spawn_blocking(move || auth::hash(&hashed_token)).await;
+    let saved = hashed.unwrap_or_else(|e| Err(e.to_string())).and_then(|hash| {
+        store.save_student(&student.id, &student.name, Some(&hash)).map_err(|e| e.to_string())
+    });
+    match saved {
+        Ok(()) => RawHtml(format!(
+            "<h2>New token for {} ({})</h2><p><code>{}</code></p><p>The old token no longer works.</p><a href='/admin/roster'>Back to the Roster</a>",
+            htmlescape::encode_minimal(&student.name),
+            htmlescape::encode_minimal(&student.id),
+            token
+        )),
+        Err(e) => RawHtml(format!("<h2>Error saving the new token: {}</h2>", e)),
+    }
+}
+
+/// GET /admin/submissions
+/// Lists every recent submission for TAs and instructors, optionally only
+/// those for one question or by one student.
+#[get("/admin/submissions?<question>&<student>")]
+async fn admin_submissions(
+    question: Option<&str>,
+    student: Option<&str>,
+    _staff: Staff,
+    store: &State<Arc<Store>>,
+) -> RawHtml<String> {
+    let question = question.filter(|question| !question.is_empty());
+    let student = student.filter(|student| !student.is_empty());
+    let summaries = match store.all_submissions(question, student) {
+        Ok(summaries) => summaries,
+        Err(e) => return RawHtml(format!("<h2>Error reading submissions: {}</h2>", e)),
+    };
+    let rows: String = summaries.iter().map(|summary| {
+        let result = match (summary.compiled, summary.grade, summary.max_grade) {
+            (true, Some(grade), Some(max_grade)) => format!("{:.2} / {:.2}", grade, max_grade),
+            _ => "Did not compile".to_string(),
+        };
+        format!(
+            "<tr><td>{} UTC</td><td>{}</td><td>{}</td><td>{}</td><td><a href='/submissions/{}'>Results</a></td></tr>",
+            summary.submitted_at,
+            htmlescape::encode_minimal(summary.student.as_deref().unwrap_or("(instructor)")),
+            htmlescape::encode_minimal(&summary.question),
+            result,
+            summary.id
+        )
+    }).collect();
+    RawHtml(format!(r#"
+    <!DOCTYPE html>
+    <html>
+      <head>
+        <meta charset="UTF-8">
+        <title>All Submissions - Autograder</title>
+        <link href="https://cdn.jsdelivr.net/npm/bootstrap@5.3.0/dist/css/bootstrap.min.css" rel="stylesheet">
+        <style>
+          body {{ background-color: #f8f9fa; }}
+        </style>
+      </head>
+      <body>
+        <div class="container mt-5">
+          <h1>All Submissions</h1>
+          <form action="/admin/submissions" method="get" class="row g-2 mb-3">
+            <div class="col-auto"><input type="text" name="question" class="form-control" placeholder="Question, e.g. q2" value="{}"></div>
+            <div class="col-auto"><input type="text" name="student" class="form-control" placeholder="Student ID" value="{}"></div>
+            <div class="col-auto"><button type="submit" class="btn btn-primary">Filter</button></div>
+          </form>
+          <table class="table">
+            <thead><tr><th>Submitted</th><th>Student</th><th>Question</th><th>Score</th><th></th></tr></thead>
+            <tbody>{}</tbody>
+          </table>
+          <a href="/admin" class="btn btn-secondary mt-3">Back to Admin Panel</a>
+        </div>
+      </body>
+    </html>
+    "#,
+        htmlescape::encode_attribute(question.unwrap_or_default()),
+        htmlescape::encode_attribute(student.unwrap_or_default()),
+        rows
+    ))
+}
+
+/// GET /admin/roles
+/// Lists everyone on the roster with a form for changing their role.
+#[get("/admin/roles")]
+async fn admin_roles_page(_instructor: Instructor, cookies: &CookieJar<'_>, store: &State<Arc<Store>>) -> RawHtml<String> {
+    let csrf_field = csrf::field(cookies);
+    let students = match store.students() {
+        Ok(students) => students,
+        Err(e) => return RawHtml(format!("<h2>Error reading the roster: {}</h2>", e)),
+    };
+    let rows: String = students.iter().map(|student| {
+        let options: String = Role::ALL.iter().map(|role| {
+            format!(
+                "<option value='{}'{}>{}</option>",
+                role.value(),
+                if *role == student.role { " selected" } else { "" },
+                role
+            )
+        }).collect();
+        format!(
+            "<tr><td>{id}</td><td>{}</td><td><form action='/admin/roles' method='post' class='d-flex gap-2 m-0'>{}<input type='hidden' name='id' value='{id}'><select name='role' class='form-select form-select-sm w-auto'>{}</select><button type='submit' class='btn btn-sm btn-outline-primary'>Save</button></form></td></tr>",
+            htmlescape::encode_minimal(&student.name),
+            csrf_field,
+            options,
+            id = htmlescape::encode_attribute(&student.id)
+        )
+    }).collect();
+    RawHtml(format!(r#"
+    <!DOCTYPE html>
+    <html>
+      <head>
+        <meta charset="UTF-8">
+        <title>Roles - Autograder</title>
+        <link href="https://cdn.jsdelivr.net/npm/bootstrap@5.3.0/dist/css/bootstrap.min.css" rel="stylesheet">
+        <style>
+          body {{ background-color: #f8f9fa; }}
+        </style>
+      </head>
+      <body>
+        <div class="container mt-5">
+          <h1>Roles</h1>
+          <p>TAs see every submission and leave feedback. Instructors also edit questions and tests and manage the roster.
+             Admins from the configuration are always instructors.</p>
+          <table class="table">
+            <thead><tr><th>ID</th><th>Name</th><th>Role</th></tr></thead>
+            <tbody>{}</tbody>
+          </table>
+          <a href="/admin" class="btn btn-secondary mt-3">Back to Admin Panel</a>
+        </div>
+      </body>
+    </html>
+    "#, rows))
+}
+
+/// Form data assigning a role to someone on the roster.
+#[derive(FromForm)]
+struct RoleAssignment {
+    id: String,
+    role: Role,
+    #[field(default = String::new())]
+    csrf_token: String,
+}
+
+impl CsrfProtected for RoleAssignment {
+    fn csrf_token(&self) -> &str {
+        &self.csrf_token
+    }
+}
+
+/// POST /admin/roles
+/// Changes someone's role.
+#[post("/admin/roles", data = "<form>")]
+async fn admin_roles_update(form: CsrfForm<RoleAssignment>, _instructor: Instructor, store: &State<Arc<Store>>) -> Result<Redirect, RawHtml<String>> {
+    match store.set_role(&form.id, form.role) {
+        Ok(true) => Ok(Redirect::to(uri!(admin_roles_page))),
+        Ok(false) => Err(RawHtml("<h2>No such student.</h2><a href='/admin/roles'>Back</a>".to_string())),
+        Err(e) => Err(RawHtml(format!("<h2>Error saving the role: {}</h2>", e))),
+    }
 }
 
 /// GET /admin/edit
 /// Returns a page for editing test cases for a given question with improved styling.
+/// With a reference solution, test cases whose stored expected output disagrees
+/// with the solution's output are flagged.
 #[get("/admin/edit?<question>")]
-async fn admin_edit_page(question: Option<String>) -> RawHtml<String> {
+async fn admin_edit_page(
+    question: Option<String>,
+    _instructor: Instructor,
+    cookies: &CookieJar<'_>,
+    jail: &State<Arc<dyn Sandbox>>,
+    profiles: &State<Arc<Profiles>>,
+    references: &State<Arc<References>>,
+) -> RawHtml<String> {
     let q = question.unwrap_or_else(|| "q1".to_string());
-    let content = fs::read_to_string("test_cases.json").unwrap_or_else(|_| "{}".to_string());
-    let mut test_cases_map: TestCasesMap = serde_json::from_str(&content).unwrap_or_else(|_| HashMap::new());
-    let cases = test_cases_map.entry(q.clone()).or_insert(Vec::new());
+    let test_cases_map = questions::load().unwrap_or_default();
+    let question = test_cases_map.get(&q).cloned().unwrap_or_default();
+
+    let mut reference_error = None;
+    let mut reference_outputs = Vec::new();
+    if question.settings.reference.is_some() {
+        let (jail, profiles, references) = (Arc::clone(jail), Arc::clone(profiles), Arc::clone(references));
+        let checked = question.clone();
+        // Running the reference solution blocks, like grading does.
+        let outputs = rocket::tokio::task::spawn_blocking(move || {
+            profiles
+                .for_question(checked.settings.sandbox_profile.as_deref())
+                .and_then(|profile| references.expected_outputs(jail.as_ref(), &checked, &checked.tests, profile.as_deref()))
+        })
+        .await;
+        match outputs.unwrap_or_else(|e| Err(format!("Running the reference solution stopped unexpectedly: {}", e))) {
+            Ok(outputs) => reference_outputs = outputs,
+            Err(e) => reference_error = Some(e),
+        }
+    }
 
     // You may wish to customize the question description here as well.
     let question_desc = match q.as_str() {
@@ -564,12 +1653,34 @@ async fn admin_edit_page(question: Option<String>) -> RawHtml<String> {
              <h1>Edit Test Cases for {} ({})</h1>
              <p class="mb-4">Modify the test cases below or add new ones as needed.</p>
              <form id="test-cases-form" action="/admin/edit" method="post">
+               {}
                <input type="hidden" name="question" value="{}">
-    "#, q, q, question_desc, q);
+               <div class="mb-3">
+                 <label>Question settings (JSON):</label>
+                 <textarea class="form-control font-monospace" name="settings" rows="3">{}</textarea>
+               </div>
+    "#, q, q, question_desc, csrf::field(cookies), q, htmlescape::encode_minimal(&question.settings.to_json()));
+    if let Some(e) = &reference_error {
+        form_html.push_str(&format!(
+            "<div class='alert alert-danger'>{}</div>",
+            htmlescape::encode_minimal(e)
+        ));
+    }
 
-    for case in cases {
+    for (i, case) in question.tests.iter().enumerate() {
+        let disagreement = reference_outputs.get(i).and_then(Option::as_ref).and_then(|output| {
+            case.matcher
+                .check(&case.expected_output, output, &case.tolerance())
+                .err()
+                .map(|difference| format!(
+                    "<div class='alert alert-warning'>The expected output disagrees with the reference solution ({}). Reference output:<pre>{}</pre></div>",
+                    htmlescape::encode_minimal(&difference),
+                    htmlescape::encode_minimal(output.trim_end())
+                ))
+        });
         form_html.push_str(&format!(r#"
            <div class="test-case">
+             {}
              <button type="button" class="btn btn-danger btn-sm remove-btn" onclick="removeTestCase(this)">X</button>
              <div class="mb-3">
                <label>Description:</label>
@@ -583,11 +1694,17 @@ async fn admin_edit_page(question: Option<String>) -> RawHtml<String> {
                <label>Expected Output:</label>
                <textarea class="form-control" name="exp" rows="3">{}</textarea>
              </div>
+             <div class="mb-3">
+               <label>Extra settings (JSON):</label>
+               <textarea class="form-control font-monospace" name="opts" rows="2">{}</textarea>
+             </div>
            </div>
         "#,
+        disagreement.unwrap_or_default(),
         htmlescape::encode_minimal(&case.description),
         htmlescape::encode_minimal(&case.input),
-        htmlescape::encode_minimal(&case.expected_output)));
+        htmlescape::encode_minimal(&case.expected_output),
+        htmlescape::encode_minimal(&case.extra_settings_json())));
     }
 
     form_html.push_str(r#"
@@ -619,6 +1736,10 @@ async fn admin_edit_page(question: Option<String>) -> RawHtml<String> {
                    <label>Expected Output:</label>
                    <textarea class="form-control" name="exp" rows="3"></textarea>
                  </div>
+                 <div class="mb-3">
+                   <label>Extra settings (JSON):</label>
+                   <textarea class="form-control font-monospace" name="opts" rows="2"></textarea>
+                 </div>
                `;
                container.appendChild(div);
              }
@@ -633,33 +1754,52 @@ async fn admin_edit_page(question: Option<String>) -> RawHtml<String> {
 #[derive(rocket::form::FromForm)]
 struct AdminEditForm {
     question: String,
+    settings: String,
     desc: Vec<String>,
     inp: Vec<String>,
     exp: Vec<String>,
+    opts: Vec<String>,
+    #[field(default = String::new())]
+    csrf_token: String,
+}
+
+impl CsrfProtected for AdminEditForm {
+    fn csrf_token(&self) -> &str {
+        &self.csrf_token
+    }
 }
 
 /// POST /admin/edit
 /// Updates the test cases for a given question and writes them back to the JSON file.
 #[post("/admin/edit", data = "<form>")]
-async fn admin_edit_update(form: Form<AdminEditForm>) -> RawHtml<String> {
+async fn admin_edit_update(form: CsrfForm<AdminEditForm>, _instructor: Instructor, profiles: &State<Arc<Profiles>>) -> RawHtml<String> {
     let q = &form.question;
-    let content = fs::read_to_string("test_cases.json").unwrap_or_else(|_| "{}".to_string());
-    let mut test_cases_map: TestCasesMap = serde_json::from_str(&content).unwrap_or_else(|_| HashMap::new());
+    let mut test_cases_map: TestCasesMap = questions::load().unwrap_or_default();
+    let settings = match questions::QuestionSettings::from_json(&form.settings) {
+        Ok(settings) => settings,
+        Err(e) => return RawHtml(format!("<h2>{}</h2><a href='/admin/edit?question={}'>Back</a>", htmlescape::encode_minimal(&e), q)),
+    };
+    let checked = match &settings.sandbox_profile {
+        Some(path) => profiles.get(path).map(|_| ()),
+        None => Ok(()),
+    }.and_then(|_| seccomp::validate(&settings.allowed_syscalls))
+    .and_then(|_| settings.validate());
+    if let Err(e) = checked {
+        return RawHtml(format!("<h2>{}</h2><a href='/admin/edit?question={}'>Back</a>", htmlescape::encode_minimal(&e), q));
+    }
     let mut new_cases = Vec::new();
-    let n = form.desc.len().min(form.inp.len()).min(form.exp.len());
+    let n = form.desc.len().min(form.inp.len()).min(form.exp.len()).min(form.opts.len());
     for i in 0..n {
-        new_cases.push(TestCase {
-            description: form.desc[i].clone(),
-            input: form.inp[i].clone(),
-            expected_output: form.exp[i].clone(),
-        });
+        match TestCase::from_form(&form.desc[i], &form.inp[i], &form.exp[i], &form.opts[i]) {
+            Ok(case) => new_cases.push(case),
+            Err(e) => return RawHtml(format!("<h2>{}</h2><a href='/admin/edit?question={}'>Back</a>", htmlescape::encode_minimal(&e), q)),
+        }
     }
-    test_cases_map.insert(q.clone(), new_cases);
-    let new_content = serde_json::to_string_pretty(&test_cases_map).unwrap_or_else(|_| "{}".to_string());
-    if let Err(e) = fs::write("test_cases.json", new_content) {
-        return RawHtml(format!("<h2>Error updating test cases: {}</h2>", e));
+    test_cases_map.insert(q.clone(), Question { settings, tests: new_cases });
+    if let Err(e) = questions::save(&test_cases_map) {
+        return RawHtml(format!("<h2>{}</h2>", e));
     }
-    
+
     RawHtml(format!("<h2>Test cases for {} updated successfully.</h2><a href='/admin'>Back to Admin Panel</a>", q))
 }
 
@@ -667,16 +1807,51 @@ async fn admin_edit_update(form: Form<AdminEditForm>) -> RawHtml<String> {
 // Launch the Application
 //
 
-#[launch]
-fn rocket() -> _ {
+#[rocket::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // `autograder hash-password` reads a password from stdin and prints the
+    // hash to put in the `admins` setting.
+    if env::args().nth(1).as_deref() == Some("hash-password") {
+        let mut password = String::new();
+        std::io::stdin().read_line(&mut password)?;
+        println!("{}", auth::hash(password.trim_end_matches(['\r', '\n']))?);
+        return Ok(());
+    }
+    rocket().launch().await?;
+    Ok(())
+}
+
+fn rocket() -> rocket::Rocket<rocket::Build> {
     rocket::build()
         .mount("/", routes![
             index, 
+            index_logged_out,
             upload, 
+            login_page,
+            login,
+            logout,
             admin_login_page, 
             admin_login, 
+            admin_logout,
+            admin_panel,
             admin_edit_page, 
-            admin_edit_update
+            admin_edit_update,
+            admin_run_page,
+            admin_run,
+            submission_page_staff,
+            submission_page,
+            submissions_page,
+            add_feedback,
+            admin_submissions,
+            admin_roles_page,
+            admin_roles_update,
+            admin_roster_page,
+            admin_roster_import,
+            admin_roster_reset
         ])
+        .register("/", catchers![unauthorized, forbidden])
+        .mount(api::BASE, api::routes())
+        .register(api::BASE, api::catchers())
         .mount("/static", FileServer::from(relative!("static")))
+        .attach(config::fairing())
 }
\ No newline at end of file
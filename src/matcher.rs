@@ -0,0 +1,231 @@
+// Ways of comparing a program's output with a test case's expected output.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// How a test case's `expected_output` is compared with what the program printed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Matcher {
+    /// The output contains the expected text.
+    #[default]
+    Contains,
+    /// The output is the expected text, ignoring trailing whitespace at the end.
+    Exact,
+    /// Same words in the same order, however they are spaced or split across lines.
+    Whitespace,
+    /// The output is the expected text, ignoring case and trailing whitespace.
+    CaseInsensitive,
+    /// The expected text is a regular expression that must match the output.
+    Regex,
+    /// Same lines in the same order, ignoring trailing whitespace on each line.
+    Lines,
+    /// Same lines in any order, ignoring blank lines and surrounding whitespace.
+    UnorderedLines,
+    /// The same numbers in the same order, each within the test's tolerance.
+    /// Any text between the numbers is ignored.
+    Numeric,
+}
+
+/// Absolute tolerance of the numeric matcher when a test sets none, enough to
+/// accept "8.00" for "8.000" but not a different value.
+const DEFAULT_ABSOLUTE_TOLERANCE: f64 = 1e-6;
+
+/// How far a number may be from the expected one under the numeric matcher. A
+/// number passes if it is within either bound.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tolerance {
+    pub absolute: Option<f64>,
+    /// Fraction of the expected value's magnitude.
+    pub relative: Option<f64>,
+}
+
+impl Tolerance {
+    fn accepts(&self, expected: f64, actual: f64) -> bool {
+        let difference = (actual - expected).abs();
+        let absolute = match (self.absolute, self.relative) {
+            (None, None) => Some(DEFAULT_ABSOLUTE_TOLERANCE),
+            (absolute, _) => absolute,
+        };
+        absolute.is_some_and(|eps| difference <= eps)
+            || self.relative.is_some_and(|eps| difference <= eps * expected.abs())
+    }
+
+    fn describe(&self) -> String {
+        match (self.absolute, self.relative) {
+            (None, None) => format!("±{}", DEFAULT_ABSOLUTE_TOLERANCE),
+            (Some(abs), None) => format!("±{}", abs),
+            (None, Some(rel)) => format!("±{}%", rel * 100.0),
+            (Some(abs), Some(rel)) => format!("±{} or ±{}%", abs, rel * 100.0),
+        }
+    }
+}
+
+impl Matcher {
+    pub fn is_default(&self) -> bool {
+        *self == Matcher::default()
+    }
+
+    /// Label for the expected output on the results page.
+    pub fn expectation(&self) -> &'static str {
+        match self {
+            Matcher::Contains => "Expected to contain",
+            Matcher::Exact => "Expected exactly",
+            Matcher::Whitespace => "Expected (ignoring whitespace)",
+            Matcher::CaseInsensitive => "Expected (ignoring case)",
+            Matcher::Regex => "Expected to match",
+            Matcher::Lines => "Expected lines",
+            Matcher::UnorderedLines => "Expected lines (any order)",
+            Matcher::Numeric => "Expected numbers",
+        }
+    }
+
+    /// Checks that `expected` is usable with this matcher.
+    pub fn validate(&self, expected: &str) -> Result<(), String> {
+        match self {
+            Matcher::Regex => Regex::new(expected).map(|_| ()).map_err(|e| format!("Invalid regex: {}", e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Compares `actual` with `expected`, describing the first difference on a mismatch.
+    pub fn check(&self, expected: &str, actual: &str, tolerance: &Tolerance) -> Result<(), String> {
+        match self {
+            Matcher::Contains => {
+                if actual.trim().contains(expected.trim()) {
+                    Ok(())
+                } else {
+                    Err("expected text not found in the output".to_string())
+                }
+            }
+            Matcher::Exact => {
+                if actual.trim_end() == expected.trim_end() {
+                    Ok(())
+                } else {
+                    Err(first_difference(expected.trim_end(), actual.trim_end()))
+                }
+            }
+            Matcher::CaseInsensitive => {
+                let (expected, actual) = (expected.trim_end().to_lowercase(), actual.trim_end().to_lowercase());
+                if actual == expected {
+                    Ok(())
+                } else {
+                    Err(first_difference(&expected, &actual))
+                }
+            }
+            Matcher::Whitespace => {
+                let expected: Vec<&str> = expected.split_whitespace().collect();
+                let actual: Vec<&str> = actual.split_whitespace().collect();
+                let position = expected.iter().zip(&actual).position(|(e, a)| e != a);
+                match position {
+                    None if expected.len() == actual.len() => Ok(()),
+                    None if expected.len() > actual.len() => {
+                        Err(format!("output ends after {} words, expected {}", actual.len(), expected.len()))
+                    }
+                    None => Err(format!("unexpected extra word \"{}\"", actual[expected.len()])),
+                    Some(i) => Err(format!("word {}: expected \"{}\", got \"{}\"", i + 1, expected[i], actual[i])),
+                }
+            }
+            Matcher::Regex => {
+                let re = Regex::new(expected).map_err(|e| format!("invalid regex: {}", e))?;
+                if re.is_match(actual) {
+                    Ok(())
+                } else {
+                    Err("output does not match the pattern".to_string())
+                }
+            }
+            Matcher::Lines => {
+                let expected: Vec<&str> = expected.trim_end().lines().map(str::trim_end).collect();
+                let actual: Vec<&str> = actual.trim_end().lines().map(str::trim_end).collect();
+                for i in 0..expected.len().max(actual.len()) {
+                    match (expected.get(i), actual.get(i)) {
+                        (Some(e), Some(a)) if e == a => {}
+                        (Some(e), Some(a)) => {
+                            return Err(format!("line {}: expected \"{}\", got \"{}\"", i + 1, e, a))
+                        }
+                        (Some(e), None) => return Err(format!("line {}: expected \"{}\", got end of output", i + 1, e)),
+                        (None, Some(a)) => return Err(format!("line {}: unexpected extra line \"{}\"", i + 1, a)),
+                        (None, None) => unreachable!(),
+                    }
+                }
+                Ok(())
+            }
+            Matcher::UnorderedLines => {
+                let mut missing: Vec<&str> = non_blank_lines(expected);
+                let mut extra = Vec::new();
+                for line in non_blank_lines(actual) {
+                    match missing.iter().position(|&e| e == line) {
+                        Some(i) => {
+                            missing.swap_remove(i);
+                        }
+                        None => extra.push(line),
+                    }
+                }
+                match (missing.first(), extra.first()) {
+                    (None, None) => Ok(()),
+                    (Some(line), _) => Err(format!("missing line \"{}\"", line)),
+                    (None, Some(line)) => Err(format!("unexpected line \"{}\"", line)),
+                }
+            }
+            Matcher::Numeric => {
+                let expected = numbers(expected);
+                let actual = numbers(actual);
+                for (i, ((e_text, e), (a_text, a))) in expected.iter().zip(&actual).enumerate() {
+                    if !tolerance.accepts(*e, *a) {
+                        return Err(format!(
+                            "number {}: expected {}, got {} (off by {}, tolerance {})",
+                            i + 1,
+                            e_text,
+                            a_text,
+                            (a - e).abs(),
+                            tolerance.describe()
+                        ));
+                    }
+                }
+                if expected.len() == actual.len() {
+                    Ok(())
+                } else {
+                    Err(format!("expected {} numbers, found {}", expected.len(), actual.len()))
+                }
+            }
+        }
+    }
+}
+
+/// Every decimal number in `text`, in order, with the text it was parsed from.
+fn numbers(text: &str) -> Vec<(&str, f64)> {
+    let re = Regex::new(r"[-+]?(?:\d+\.?\d*|\.\d+)(?:[eE][-+]?\d+)?").expect("number pattern is valid");
+    re.find_iter(text)
+        .filter_map(|m| m.as_str().parse().ok().map(|value| (m.as_str(), value)))
+        .collect()
+}
+
+fn non_blank_lines(text: &str) -> Vec<&str> {
+    text.lines().map(str::trim).filter(|line| !line.is_empty()).collect()
+}
+
+/// Describes where two texts first differ, by line and column.
+fn first_difference(expected: &str, actual: &str) -> String {
+    let mut line = 1;
+    let mut column = 1;
+    let mut e = expected.chars();
+    let mut a = actual.chars();
+    loop {
+        match (e.next(), a.next()) {
+            (Some(x), Some(y)) if x == y => {
+                if x == '\n' {
+                    line += 1;
+                    column = 1;
+                } else {
+                    column += 1;
+                }
+            }
+            (Some(x), Some(y)) => {
+                return format!("line {}, column {}: expected {:?}, got {:?}", line, column, x, y)
+            }
+            (Some(_), None) => return format!("output ends early at line {}, column {}", line, column),
+            (None, Some(y)) => return format!("unexpected extra output at line {}, column {}: {:?}", line, column, y),
+            (None, None) => return "outputs are identical".to_string(),
+        }
+    }
+}
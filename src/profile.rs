@@ -0,0 +1,205 @@
+// Sandbox profiles: jail options kept in files instead of in the runner.
+//
+// A profile is either an NSJail protobuf text config (`*.cfg`), passed to nsjail
+// with `--config`, or a TOML file whose settings every backend translates into
+// its own arguments. Profiles are loaded and validated at launch.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use crate::sandbox::Backend;
+
+/// Jail settings from a TOML profile. All of them are optional.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TomlProfile {
+    /// Extra host directories visible read-only inside the jail.
+    #[serde(default)]
+    pub read_only_mounts: Vec<PathBuf>,
+    /// Extra host directories the program may write to.
+    #[serde(default)]
+    pub writable_mounts: Vec<PathBuf>,
+    /// Empty scratch directories mounted as tmpfs.
+    #[serde(default)]
+    pub tmpfs_mounts: Vec<PathBuf>,
+    /// User and group the program runs as inside the jail.
+    pub user: Option<u32>,
+    pub group: Option<u32>,
+    pub hostname: Option<String>,
+    /// cgroup limits (nsjail only): memory in bytes, process count, CPU share.
+    pub cgroup_mem_max: Option<u64>,
+    pub cgroup_pids_max: Option<u64>,
+    pub cgroup_cpu_ms_per_sec: Option<u64>,
+    /// Arguments appended verbatim to the backend's command line.
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+}
+
+/// A loaded sandbox profile.
+#[derive(Debug, Clone)]
+pub enum Profile {
+    /// NSJail protobuf text config, passed with `--config`.
+    NsjailConfig(PathBuf),
+    Toml(TomlProfile),
+}
+
+impl Profile {
+    /// Loads and validates the profile at `path` for use with `backend`.
+    pub fn load(path: &Path, backend: Backend) -> Result<Profile, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read sandbox profile {}: {}", path.display(), e))?;
+        let profile = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => {
+                let toml: TomlProfile = toml::from_str(&content)
+                    .map_err(|e| format!("Invalid sandbox profile {}: {}", path.display(), e))?;
+                let mounts = toml.read_only_mounts.iter().chain(&toml.writable_mounts);
+                if let Some(missing) = mounts.into_iter().find(|mount| !mount.exists()) {
+                    return Err(format!(
+                        "Sandbox profile {} mounts {}, which does not exist",
+                        path.display(),
+                        missing.display()
+                    ));
+                }
+                Profile::Toml(toml)
+            }
+            Some("cfg") => {
+                if backend != Backend::Nsjail {
+                    return Err(format!(
+                        "Sandbox profile {} is an nsjail config, but the sandbox backend is {:?}",
+                        path.display(),
+                        backend
+                    ));
+                }
+                check_protobuf_text(&content)
+                    .map_err(|e| format!("Invalid nsjail config {}: {}", path.display(), e))?;
+                Profile::NsjailConfig(path.to_path_buf())
+            }
+            _ => {
+                return Err(format!(
+                    "Sandbox profile {} must be a .toml profile or an nsjail .cfg file",
+                    path.display()
+                ))
+            }
+        };
+        Ok(profile)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word,
+    Str,
+    Colon,
+    Open,
+    Close,
+}
+
+/// Splits protobuf text format into tokens, each with its line number.
+fn tokenize(content: &str) -> Result<Vec<(Token, usize)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = content.chars().peekable();
+    let mut line = 1;
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '#' => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            ':' => tokens.push((Token::Colon, line)),
+            '{' => tokens.push((Token::Open, line)),
+            '}' => tokens.push((Token::Close, line)),
+            '"' | '\'' => {
+                let start = line;
+                loop {
+                    match chars.next() {
+                        Some('\\') => {
+                            chars.next();
+                        }
+                        Some(q) if q == c => break,
+                        Some('\n') | None => return Err(format!("line {}: unterminated string", start)),
+                        Some(_) => {}
+                    }
+                }
+                tokens.push((Token::Str, start));
+            }
+            _ => {
+                while chars.next_if(|&c| !c.is_whitespace() && !"{}:#\"'".contains(c)).is_some() {}
+                tokens.push((Token::Word, line));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Checks the overall shape of a protobuf text-format message: `name: value`
+/// fields and `name { ... }` sub-messages with balanced braces. Field names and
+/// values are left for nsjail to check.
+fn check_protobuf_text(content: &str) -> Result<(), String> {
+    let tokens = tokenize(content)?;
+    let mut depth = 0usize;
+    let mut iter = tokens.iter().peekable();
+    while let Some((token, line)) = iter.next() {
+        match token {
+            Token::Close => {
+                depth = depth.checked_sub(1).ok_or(format!("line {}: unmatched '}}'", line))?;
+            }
+            Token::Word => {
+                let colon = iter.next_if(|(t, _)| *t == Token::Colon).is_some();
+                match iter.next() {
+                    Some((Token::Open, _)) => depth += 1,
+                    Some((Token::Word, _)) if colon => {}
+                    Some((Token::Str, _)) if colon => {
+                        // Adjacent strings are concatenated.
+                        while iter.next_if(|(t, _)| *t == Token::Str).is_some() {}
+                    }
+                    _ => return Err(format!("line {}: expected ': value' or '{{' after field name", line)),
+                }
+            }
+            _ => return Err(format!("line {}: expected a field name", line)),
+        }
+    }
+    if depth != 0 {
+        return Err("unclosed '{'".to_string());
+    }
+    Ok(())
+}
+
+/// Profiles by path, loaded once and shared between requests.
+pub struct Profiles {
+    backend: Backend,
+    /// Applies to questions that don't name a profile of their own.
+    default: Option<Arc<Profile>>,
+    loaded: RwLock<HashMap<String, Arc<Profile>>>,
+}
+
+impl Profiles {
+    pub fn new(backend: Backend, default_path: Option<&str>) -> Result<Profiles, String> {
+        let mut profiles = Profiles { backend, default: None, loaded: RwLock::new(HashMap::new()) };
+        if let Some(path) = default_path {
+            profiles.default = Some(profiles.get(path)?);
+        }
+        Ok(profiles)
+    }
+
+    /// The profile at `path`, loading it on first use.
+    pub fn get(&self, path: &str) -> Result<Arc<Profile>, String> {
+        if let Some(profile) = self.loaded.read().unwrap().get(path) {
+            return Ok(Arc::clone(profile));
+        }
+        let profile = Arc::new(Profile::load(Path::new(path), self.backend)?);
+        self.loaded.write().unwrap().insert(path.to_string(), Arc::clone(&profile));
+        Ok(profile)
+    }
+
+    /// The profile a question uses: its own if it names one, otherwise the default.
+    pub fn for_question(&self, path: Option<&str>) -> Result<Option<Arc<Profile>>, String> {
+        match path {
+            Some(path) => self.get(path).map(Some),
+            None => Ok(self.default.clone()),
+        }
+    }
+}
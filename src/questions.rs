@@ -0,0 +1,441 @@
+// Test case definitions and loading/saving of `test_cases.json`.
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::generator::Generator;
+use crate::interaction::{self, Step};
+use crate::matcher::{Matcher, Tolerance};
+use crate::reference;
+use crate::runner::Limits;
+
+/// Path of the JSON file holding every question's test cases.
+pub const TEST_CASES_PATH: &str = "test_cases.json";
+
+/// Time limit applied when neither the test case nor the question sets one.
+pub const DEFAULT_TIME_LIMIT_MS: u64 = 2000;
+/// Default resource limits for questions that don't set their own.
+pub const DEFAULT_MEMORY_LIMIT_MB: u64 = 256;
+pub const DEFAULT_MAX_PROCESSES: u64 = 16;
+pub const DEFAULT_FILE_SIZE_LIMIT_MB: u64 = 1;
+pub const DEFAULT_OUTPUT_LIMIT_KB: u64 = 64;
+pub const DEFAULT_STDERR_LIMIT_KB: u64 = 16;
+/// Compiling gets more room than running: gcc spawns several helper processes.
+pub const DEFAULT_COMPILE_TIME_LIMIT_MS: u64 = 10_000;
+const COMPILE_MEMORY_LIMIT_MB: u64 = 512;
+const COMPILE_MAX_PROCESSES: u64 = 32;
+const COMPILE_FILE_SIZE_LIMIT_MB: u64 = 64;
+
+/// Data structure representing a single test case.
+/// Each test case includes a description, input string, and expected output.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TestCase {
+    pub description: String,
+    pub input: String,
+    pub expected_output: String,
+    /// How `expected_output` is compared with the program's output.
+    #[serde(default, skip_serializing_if = "Matcher::is_default")]
+    pub matcher: Matcher,
+    /// Absolute and relative (fraction of the expected value) tolerance for the numeric matcher.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abs_tolerance: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rel_tolerance: Option<f64>,
+    /// Command-line arguments passed to the program.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// Environment variables the program sees; nothing else from the server's
+    /// environment is passed on.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Files, by name, placed in the program's working directory before it runs.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, String>,
+    /// Files, by name, the program must leave in its working directory, with their exact contents.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub expected_files: BTreeMap<String, String>,
+    /// Scripted conversation played over stdin and stdout instead of writing `input` up front.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub interaction: Vec<Step>,
+    /// Checker program that judges the output instead of the matcher.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checker: Option<String>,
+    /// How much of this test students see on the results page.
+    #[serde(default, skip_serializing_if = "Visibility::is_default")]
+    pub visibility: Visibility,
+    /// Weight of this test in the question's score; 1 if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub points: Option<f64>,
+    /// Named group, such as "edge cases", whose points are totalled together.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Wall-clock and CPU time limit for this test, overriding the question's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_limit_ms: Option<u64>,
+    /// Text the program's stderr must contain, if set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_stderr: Option<String>,
+    /// Exit code the program must return. Any exit code is accepted if unset,
+    /// but being killed by a signal always fails the test.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_exit_code: Option<i32>,
+}
+
+/// How much of a test case students see on the results page. Instructors
+/// always see everything.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    /// Input, expected and actual output, and everything else about the run.
+    #[default]
+    Visible,
+    /// Only the test's description and whether it passed.
+    NameOnly,
+    /// Only counted in an aggregate of hidden tests that passed.
+    Hidden,
+}
+
+impl Visibility {
+    pub fn is_default(&self) -> bool {
+        *self == Visibility::Visible
+    }
+}
+
+/// Question-level settings shared by all of a question's test cases.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct QuestionSettings {
+    /// Wall-clock and CPU time limit for each test case.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_limit_ms: Option<u64>,
+    /// Peak resident memory a test may use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit_mb: Option<u64>,
+    /// Processes and threads the program may have running at once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_processes: Option<u64>,
+    /// Largest file the program may write.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_size_limit_mb: Option<u64>,
+    /// Stdout captured before the program is killed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_limit_kb: Option<u64>,
+    /// Stderr kept for display; anything beyond it is discarded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr_limit_kb: Option<u64>,
+    /// Wall-clock and CPU time limit for compiling the submission.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compile_time_limit_ms: Option<u64>,
+    /// Most points each named group of tests can contribute to the score.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub group_caps: BTreeMap<String, f64>,
+    /// Grade for full marks; the points earned are scaled onto it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_points: Option<f64>,
+    /// Sandbox profile file used instead of the globally configured one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox_profile: Option<String>,
+    /// Instructor C solution whose output replaces every test's `expected_output`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    /// Random inputs added to every submission's tests, judged against the reference solution.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generator: Option<Generator>,
+    /// System calls, or groups such as "file_io", allowed on top of the default seccomp policy.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_syscalls: Vec<String>,
+}
+
+/// A question's settings together with its test cases.
+///
+/// In `test_cases.json` a question is either a bare list of test cases or an
+/// object with a `tests` list next to its settings. Questions without any
+/// settings are written back as a bare list.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(from = "QuestionRepr", into = "QuestionRepr")]
+pub struct Question {
+    pub settings: QuestionSettings,
+    pub tests: Vec<TestCase>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum QuestionRepr {
+    Tests(Vec<TestCase>),
+    Full {
+        #[serde(flatten)]
+        settings: Box<QuestionSettings>,
+        tests: Vec<TestCase>,
+    },
+}
+
+// Not derived: `deny_unknown_fields` has no effect on flattened fields, so the
+// settings are read from whatever is left once `tests` is taken out.
+impl<'de> Deserialize<'de> for QuestionRepr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::Object(mut fields) => {
+                let tests = fields.remove("tests").ok_or_else(|| D::Error::missing_field("tests"))?;
+                let tests = serde_json::from_value(tests).map_err(D::Error::custom)?;
+                let settings = serde_json::from_value(Value::Object(fields)).map_err(D::Error::custom)?;
+                Ok(QuestionRepr::Full { settings, tests })
+            }
+            tests => serde_json::from_value(tests).map(QuestionRepr::Tests).map_err(D::Error::custom),
+        }
+    }
+}
+
+impl From<QuestionRepr> for Question {
+    fn from(repr: QuestionRepr) -> Self {
+        match repr {
+            QuestionRepr::Tests(tests) => Question { settings: QuestionSettings::default(), tests },
+            QuestionRepr::Full { settings, tests } => Question { settings: *settings, tests },
+        }
+    }
+}
+
+impl From<Question> for QuestionRepr {
+    fn from(question: Question) -> Self {
+        if question.settings == QuestionSettings::default() {
+            QuestionRepr::Tests(question.tests)
+        } else {
+            QuestionRepr::Full { settings: Box::new(question.settings), tests: question.tests }
+        }
+    }
+}
+
+impl Question {
+    /// Time limit for `case`, falling back to the question's and then the global default.
+    pub fn time_limit_ms(&self, case: &TestCase) -> u64 {
+        case.time_limit_ms
+            .or(self.settings.time_limit_ms)
+            .unwrap_or(DEFAULT_TIME_LIMIT_MS)
+    }
+
+    /// All resource limits that apply when running `case`. Limits too large to
+    /// express in bytes are capped rather than wrapped around.
+    pub fn limits(&self, case: &TestCase) -> Limits {
+        let time = Duration::from_millis(self.time_limit_ms(case));
+        let s = &self.settings;
+        Limits {
+            wall_time: time,
+            cpu_time: time,
+            memory: s.memory_limit_mb.unwrap_or(DEFAULT_MEMORY_LIMIT_MB).saturating_mul(1024 * 1024),
+            processes: s.max_processes.unwrap_or(DEFAULT_MAX_PROCESSES),
+            file_size: s.file_size_limit_mb.unwrap_or(DEFAULT_FILE_SIZE_LIMIT_MB).saturating_mul(1024 * 1024),
+            output: s.output_limit_kb.unwrap_or(DEFAULT_OUTPUT_LIMIT_KB).saturating_mul(1024) as usize,
+            stderr: s.stderr_limit_kb.unwrap_or(DEFAULT_STDERR_LIMIT_KB).saturating_mul(1024) as usize,
+        }
+    }
+
+    /// Resource limits for compiling a submission to this question.
+    pub fn compile_limits(&self) -> Limits {
+        let time = Duration::from_millis(
+            self.settings.compile_time_limit_ms.unwrap_or(DEFAULT_COMPILE_TIME_LIMIT_MS),
+        );
+        Limits {
+            wall_time: time,
+            cpu_time: time,
+            memory: COMPILE_MEMORY_LIMIT_MB * 1024 * 1024,
+            processes: COMPILE_MAX_PROCESSES,
+            file_size: COMPILE_FILE_SIZE_LIMIT_MB * 1024 * 1024,
+            output: (DEFAULT_OUTPUT_LIMIT_KB * 1024) as usize,
+            stderr: (DEFAULT_OUTPUT_LIMIT_KB * 1024) as usize,
+        }
+    }
+}
+
+/// Type alias for a mapping from question IDs to their settings and test cases.
+pub type TestCasesMap = HashMap<String, Question>;
+
+/// Reads and parses the test cases file.
+pub fn load() -> Result<TestCasesMap, String> {
+    let data = fs::read_to_string(TEST_CASES_PATH)
+        .map_err(|e| format!("Error reading test cases file: {}", e))?;
+    serde_json::from_str(&data).map_err(|e| format!("Error parsing test cases file: {}", e))
+}
+
+/// Writes the test cases file back to disk.
+pub fn save(map: &TestCasesMap) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(map)
+        .map_err(|e| format!("Error serializing test cases: {}", e))?;
+    fs::write(TEST_CASES_PATH, content).map_err(|e| format!("Error updating test cases: {}", e))
+}
+
+/// Fields edited through their own inputs on the admin page; everything else is
+/// edited as a JSON object of extra settings.
+const CORE_FIELDS: [&str; 3] = ["description", "input", "expected_output"];
+
+impl TestCase {
+    pub fn points(&self) -> f64 {
+        self.points.unwrap_or(1.0)
+    }
+
+    /// Whether a reference solution's output can stand in for `expected_output`.
+    /// Interactive tests have no single input to run it on, and a regex matcher
+    /// expects a pattern rather than literal output.
+    pub fn takes_reference_output(&self) -> bool {
+        self.interaction.is_empty() && self.matcher != Matcher::Regex
+    }
+
+    pub fn tolerance(&self) -> Tolerance {
+        Tolerance { absolute: self.abs_tolerance, relative: self.rel_tolerance }
+    }
+
+    /// Checks that every input and expected file is a plain name inside the
+    /// working directory.
+    pub fn validate_file_names(&self) -> Result<(), String> {
+        for name in self.files.keys().chain(self.expected_files.keys()) {
+            if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+                return Err(format!("\"{}\" is not a valid file name", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that every environment variable has a name the program can look up.
+    pub fn validate_env(&self) -> Result<(), String> {
+        match self.env.keys().find(|name| name.is_empty() || name.contains('=') || name.contains('\0')) {
+            Some(name) => Err(format!("\"{}\" is not a valid environment variable name", name)),
+            None => Ok(()),
+        }
+    }
+
+    /// The test's environment in the form sandbox jobs take.
+    pub fn env_vars(&self) -> Vec<(&str, String)> {
+        self.env.iter().map(|(name, value)| (name.as_str(), value.clone())).collect()
+    }
+
+    /// Empties `dir`, creating it if needed, and writes the test's input files into it.
+    pub fn write_files(&self, dir: &Path) -> io::Result<()> {
+        self.validate_file_names().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+        fs::create_dir_all(dir)?;
+        for (name, contents) in &self.files {
+            fs::write(dir.join(name), contents)?;
+        }
+        Ok(())
+    }
+
+    /// Settings other than the core fields as pretty-printed JSON, or an empty
+    /// string if the test case has none.
+    pub fn extra_settings_json(&self) -> String {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let Some(obj) = value.as_object_mut() {
+            for field in CORE_FIELDS {
+                obj.remove(field);
+            }
+            if obj.is_empty() {
+                return String::new();
+            }
+        }
+        serde_json::to_string_pretty(&value).unwrap_or_default()
+    }
+
+    /// Builds a test case from the admin form's fields and its extra settings JSON.
+    pub fn from_form(description: &str, input: &str, expected_output: &str, extra: &str) -> Result<TestCase, String> {
+        let mut value = if extra.trim().is_empty() {
+            serde_json::Value::Object(Default::default())
+        } else {
+            serde_json::from_str(extra).map_err(|e| format!("Invalid settings for \"{}\": {}", description, e))?
+        };
+        let obj = value.as_object_mut()
+            .ok_or_else(|| format!("Settings for \"{}\" must be a JSON object", description))?;
+        obj.insert("description".into(), description.into());
+        obj.insert("input".into(), input.into());
+        obj.insert("expected_output".into(), expected_output.into());
+        let case: TestCase = serde_json::from_value(value)
+            .map_err(|e| format!("Invalid settings for \"{}\": {}", description, e))?;
+        case.matcher
+            .validate(&case.expected_output)
+            .map_err(|e| format!("Invalid expected output for \"{}\": {}", description, e))?;
+        case.validate_file_names()
+            .map_err(|e| format!("Invalid files for \"{}\": {}", description, e))?;
+        case.validate_env()
+            .map_err(|e| format!("Invalid environment for \"{}\": {}", description, e))?;
+        interaction::validate(&case.interaction)
+            .map_err(|e| format!("Invalid interaction for \"{}\": {}", description, e))?;
+        if case.points() < 0.0 || !case.points().is_finite() {
+            return Err(format!("Points for \"{}\" must be a non-negative number", description));
+        }
+        if let Some(checker) = &case.checker {
+            if !Path::new(checker).is_file() {
+                return Err(format!("Checker {} for \"{}\" does not exist", checker, description));
+            }
+        }
+        Ok(case)
+    }
+}
+
+impl QuestionSettings {
+    /// The settings as pretty-printed JSON, or an empty string if none are set.
+    pub fn to_json(&self) -> String {
+        if *self == QuestionSettings::default() {
+            return String::new();
+        }
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// Checks the settings that refer to other files or depend on each other.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(path) = &self.reference {
+            reference::validate(path)?;
+        }
+        if let Some(generator) = &self.generator {
+            if self.reference.is_none() {
+                return Err("A question with a generator needs a reference solution".to_string());
+            }
+            generator.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Parses settings entered on the admin page; an empty string means defaults.
+    pub fn from_json(json: &str) -> Result<QuestionSettings, String> {
+        if json.trim().is_empty() {
+            return Ok(QuestionSettings::default());
+        }
+        serde_json::from_str(json).map_err(|e| format!("Invalid question settings: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_misspelled_test_case_key() {
+        let json = r#"{"q1": [{"description": "d", "input": "1", "expected_output": "1", "time_limt_ms": 500}]}"#;
+        let error = serde_json::from_str::<TestCasesMap>(json).unwrap_err().to_string();
+        assert!(error.contains("unknown field `time_limt_ms`"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_misspelled_question_setting() {
+        let json = r#"{"q1": {"memory_limt_mb": 64, "tests": []}}"#;
+        let error = serde_json::from_str::<TestCasesMap>(json).unwrap_err().to_string();
+        assert!(error.contains("unknown field `memory_limt_mb`"), "{}", error);
+    }
+
+    #[test]
+    fn reads_settings_next_to_tests() {
+        let json = r#"{"q1": {"memory_limit_mb": 64, "tests": [{"description": "d", "input": "1", "expected_output": "1"}]}}"#;
+        let map: TestCasesMap = serde_json::from_str(json).unwrap();
+        assert_eq!(map["q1"].settings.memory_limit_mb, Some(64));
+        assert_eq!(map["q1"].tests.len(), 1);
+    }
+
+    #[test]
+    fn loads_shipped_test_cases() {
+        load().unwrap();
+    }
+}
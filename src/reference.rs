@@ -0,0 +1,172 @@
+// Instructor reference solutions, whose output is the expected output of every
+// test that compares plain output.
+//
+// A question's reference C file is compiled the first time it is needed and again
+// whenever the file changes. Its output for each input is kept alongside the
+// executable, so each input is only run once per version of the solution.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::compiler;
+use crate::profile::Profile;
+use crate::questions::{Question, TestCase};
+use crate::runner::Termination;
+use crate::sandbox::{self, Job, Mount, Sandbox};
+use crate::seccomp;
+
+/// A compiled reference solution.
+struct Reference {
+    /// Modification time of the source it was compiled from.
+    modified: SystemTime,
+    dir: PathBuf,
+    executable: PathBuf,
+    /// Output for each input, arguments and input files it has been run with.
+    outputs: Mutex<HashMap<String, String>>,
+}
+
+impl Drop for Reference {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Compiled reference solutions by source path, shared between requests.
+pub struct References {
+    /// Directory the reference solutions are compiled in.
+    build_dir: PathBuf,
+    compiled: Mutex<HashMap<String, Arc<Reference>>>,
+}
+
+impl References {
+    pub fn new(build_dir: PathBuf) -> References {
+        // Builds left behind by an earlier run are never used again.
+        let _ = fs::remove_dir_all(&build_dir);
+        References { build_dir, compiled: Mutex::new(HashMap::new()) }
+    }
+
+    /// The reference solution's output for each of `cases`, in order, or `None`
+    /// for cases that don't take it (see [`TestCase::takes_reference_output`]).
+    /// Only the outputs for the question's own test cases are kept for later
+    /// requests; generated inputs are rarely seen twice.
+    pub fn expected_outputs(
+        &self,
+        jail: &dyn Sandbox,
+        question: &Question,
+        cases: &[TestCase],
+        profile: Option<&Profile>,
+    ) -> Result<Vec<Option<String>>, String> {
+        let Some(source) = &question.settings.reference else {
+            return Err("The question has no reference solution".to_string());
+        };
+        let reference = self.compiled(jail, source, question, profile)?;
+        let policy = seccomp::policy(&question.settings.allowed_syscalls);
+        let work_dir = reference.dir.join("work");
+        // A directory of its own, since other requests may be running the same solution.
+        let run_dir = reference.dir.join(uuid::Uuid::new_v4().to_string());
+        let log_file = run_dir.with_extension("log");
+        let mut outputs = Vec::new();
+        for case in cases {
+            if !case.takes_reference_output() {
+                outputs.push(None);
+                continue;
+            }
+            let key = cache_key(case);
+            if let Some(output) = reference.outputs.lock().unwrap().get(&key) {
+                outputs.push(Some(output.clone()));
+                continue;
+            }
+            case.write_files(&run_dir)
+                .map_err(|e| format!("Cannot prepare the files of \"{}\": {}", case.description, e))?;
+            let limits = question.limits(case);
+            let mut mounts = sandbox::runtime_mounts(&run_dir);
+            mounts.push(Mount::read_only(&work_dir));
+            let env = case.env_vars();
+            let job = Job {
+                program: &reference.executable,
+                args: &case.args,
+                mounts: &mounts,
+                cwd: Some(&run_dir),
+                env: &env,
+                limits: &limits,
+                profile,
+                seccomp_policy: Some(&policy),
+                log_file: Some(&log_file),
+            };
+            let outcome = jail.run(&job, case.input.as_bytes());
+            let _ = fs::remove_dir_all(&run_dir);
+            let outcome =
+                outcome.map_err(|e| format!("Cannot run the reference solution with {}: {}", jail.name(), e))?;
+            let termination = jail.termination(&outcome);
+            // Tests may expect a nonzero exit code, so only crashes and limits count as failures.
+            if outcome.exceeded_time(&limits) || outcome.killed_on_output || matches!(termination, Termination::Signaled(_)) {
+                return Err(format!(
+                    "The reference solution failed on \"{}\" ({})",
+                    case.description,
+                    termination.describe()
+                ));
+            }
+            let output = String::from_utf8_lossy(&outcome.stdout).into_owned();
+            if question.tests.iter().any(|test| cache_key(test) == key) {
+                reference.outputs.lock().unwrap().insert(key, output.clone());
+            }
+            outputs.push(Some(output));
+        }
+        Ok(outputs)
+    }
+
+    /// The compiled reference at `source`, compiling it if it is new or has changed.
+    fn compiled(
+        &self,
+        jail: &dyn Sandbox,
+        source: &str,
+        question: &Question,
+        profile: Option<&Profile>,
+    ) -> Result<Arc<Reference>, String> {
+        let modified = fs::metadata(source)
+            .and_then(|meta| meta.modified())
+            .map_err(|e| format!("Cannot read reference solution {}: {}", source, e))?;
+        // Held while compiling so that concurrent submissions compile it only once.
+        let mut compiled = self.compiled.lock().unwrap();
+        if let Some(reference) = compiled.get(source) {
+            if reference.modified == modified {
+                return Ok(Arc::clone(reference));
+            }
+        }
+
+        let dir = self.build_dir.join(uuid::Uuid::new_v4().to_string());
+        let work_dir = dir.join("work");
+        fs::create_dir_all(&work_dir)
+            .and_then(|_| fs::copy(source, work_dir.join("main.c")))
+            .map_err(|e| format!("Cannot copy reference solution {}: {}", source, e))?;
+        let log_file = dir.join("sandbox.log");
+        let limits = question.compile_limits();
+        let executable = match compiler::compile(jail, &work_dir, &limits, profile, &log_file) {
+            Ok(compiled) => compiled.executable,
+            Err(e) => {
+                let _ = fs::remove_dir_all(&dir);
+                return Err(format!("Reference solution {} does not compile: {}", source, e));
+            }
+        };
+        let reference = Arc::new(Reference { modified, dir, executable, outputs: Mutex::new(HashMap::new()) });
+        compiled.insert(source.to_string(), Arc::clone(&reference));
+        Ok(reference)
+    }
+}
+
+/// Everything about a test case that the solution's output depends on.
+fn cache_key(case: &TestCase) -> String {
+    format!("{:?}", (&case.input, &case.args, &case.env, &case.files))
+}
+
+/// Whether `path` names an existing reference solution file.
+pub fn validate(path: &str) -> Result<(), String> {
+    if Path::new(path).is_file() {
+        Ok(())
+    } else {
+        Err(format!("Reference solution {} does not exist", path))
+    }
+}
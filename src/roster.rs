@@ -0,0 +1,78 @@
+// Class rosters imported from CSV files.
+//
+// A roster has a header row naming at least an `id` column, the student's
+// login, and a `name` column; other columns, such as an email address, are
+// ignored. Column names are matched case-insensitively, and `student_id` is
+// accepted for `id`, as exported by most learning management systems.
+
+use crate::auth;
+use crate::store::Store;
+
+/// One student listed in a roster.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RosterEntry {
+    pub id: String,
+    pub name: String,
+}
+
+/// Reads a roster, rejecting it as a whole if any row is unusable.
+pub fn parse(csv_text: &str) -> Result<Vec<RosterEntry>, String> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(csv_text.as_bytes());
+    let headers = reader.headers().map_err(|e| format!("Cannot read the roster's header: {}", e))?.clone();
+    let column = |names: &[&str]| headers.iter().position(|header| names.iter().any(|name| header.eq_ignore_ascii_case(name)));
+    let id_column = column(&["id", "student_id"]).ok_or("The roster has no id column")?;
+    let name_column = column(&["name"]).ok_or("The roster has no name column")?;
+
+    let mut entries: Vec<RosterEntry> = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        // Line 1 is the header.
+        let line = i + 2;
+        let record = record.map_err(|e| format!("Line {}: {}", line, e))?;
+        let id = record.get(id_column).unwrap_or_default();
+        let name = record.get(name_column).unwrap_or_default();
+        if id.is_empty() {
+            return Err(format!("Line {}: the student has no id", line));
+        }
+        if id.chars().any(char::is_whitespace) {
+            return Err(format!("Line {}: id {:?} contains whitespace", line, id));
+        }
+        if entries.iter().any(|entry| entry.id == id) {
+            return Err(format!("Line {}: id {} is listed twice", line, id));
+        }
+        entries.push(RosterEntry { id: id.to_string(), name: name.to_string() });
+    }
+    Ok(entries)
+}
+
+/// A roster entry after importing it.
+#[derive(Debug, Clone)]
+pub struct Imported {
+    pub entry: RosterEntry,
+    /// The login token of a student who was not on the roster before. Only its
+    /// hash is stored, so this is the only time it can be shown.
+    pub token: Option<String>,
+}
+
+/// Adds the students in `entries` who are new, each with a fresh login token,
+/// and updates the names of the others, who keep their tokens. The roster is
+/// saved in one transaction, so an import that fails changes nothing.
+pub fn import(store: &Store, entries: Vec<RosterEntry>) -> Result<Vec<Imported>, String> {
+    let mut imported = Vec::new();
+    let mut token_hashes = Vec::new();
+    for entry in entries {
+        let existing = store.student(&entry.id).map_err(|e| format!("Cannot read student {}: {}", entry.id, e))?;
+        let token = match existing {
+            Some(_) => None,
+            None => Some(auth::new_token()),
+        };
+        token_hashes.push(token.as_deref().map(auth::hash).transpose()?);
+        imported.push(Imported { entry, token });
+    }
+    let students: Vec<_> = imported
+        .iter()
+        .zip(&token_hashes)
+        .map(|(student, token_hash)| (student.entry.id.as_str(), student.entry.name.as_str(), token_hash.as_deref()))
+        .collect();
+    store.save_students(&students).map_err(|e| format!("Cannot save the roster: {}", e))?;
+    Ok(imported)
+}
@@ -0,0 +1,281 @@
+// Running a sandboxed program with a deadline and collecting its output and resource usage.
+
+use std::io::{self, Read, Write};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::interaction::{self, Step, Transcript};
+
+/// How often the runner polls a child for termination.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Resource limits for a single run. The sandbox enforces them as rlimits;
+/// the runner additionally enforces the wall-clock and output limits and checks
+/// CPU time and peak memory after the fact.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Elapsed real time after which the process is killed.
+    pub wall_time: Duration,
+    /// CPU time (user + system) the process may consume.
+    pub cpu_time: Duration,
+    /// Peak resident memory, in bytes.
+    pub memory: u64,
+    /// Processes and threads that may exist at once.
+    pub processes: u64,
+    /// Largest file the process may write, in bytes.
+    pub file_size: u64,
+    /// Stdout, in bytes, after which the process is killed.
+    pub output: usize,
+    /// Stderr, in bytes, kept for display; the rest is discarded.
+    pub stderr: usize,
+}
+
+/// How a process ended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Termination {
+    Exited(i32),
+    Signaled(i32),
+}
+
+impl Termination {
+    /// Reads a wait status as the kernel reports it.
+    pub fn from_status(status: ExitStatus) -> Self {
+        match (status.code(), status.signal()) {
+            (Some(code), _) => Termination::Exited(code),
+            (None, Some(signal)) => Termination::Signaled(signal),
+            (None, None) => Termination::Exited(-1),
+        }
+    }
+
+    /// Reads the status of a jail that reports a program killed by a signal the
+    /// way shells do, as exit code 128 + signal.
+    pub fn from_shell_convention(status: ExitStatus) -> Self {
+        match Termination::from_status(status) {
+            Termination::Exited(code) if code > 128 && code <= 128 + 64 => Termination::Signaled(code - 128),
+            other => other,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match *self {
+            Termination::Exited(code) => format!("exit code {}", code),
+            Termination::Signaled(signal) => format!("signal {} ({})", signal, signal_name(signal)),
+        }
+    }
+}
+
+/// Human-readable name of `signal`, as shells print it.
+pub fn signal_name(signal: i32) -> &'static str {
+    match signal {
+        libc::SIGSEGV => "Segmentation fault",
+        libc::SIGFPE => "Floating point exception",
+        libc::SIGABRT => "Aborted",
+        libc::SIGKILL => "Killed",
+        libc::SIGBUS => "Bus error",
+        libc::SIGILL => "Illegal instruction",
+        libc::SIGTERM => "Terminated",
+        libc::SIGPIPE => "Broken pipe",
+        libc::SIGXCPU => "CPU time limit exceeded",
+        libc::SIGXFSZ => "File size limit exceeded",
+        libc::SIGSYS => "Bad system call",
+        libc::SIGTRAP => "Trace/breakpoint trap",
+        _ => "Unknown signal",
+    }
+}
+
+/// Everything observed about a finished run.
+#[derive(Debug)]
+pub struct RunOutcome {
+    pub stdout: Vec<u8>,
+    /// Stderr, truncated to the stderr limit.
+    pub stderr: Vec<u8>,
+    /// Diagnostics the sandbox wrote about the run, kept apart from the program's stderr.
+    pub sandbox_log: String,
+    pub status: ExitStatus,
+    /// Whether the runner killed the process for exceeding its wall-clock limit.
+    pub killed_on_timeout: bool,
+    /// Whether the runner killed the process for writing too much to stdout.
+    pub killed_on_output: bool,
+    pub cpu_time: Duration,
+    pub wall_time: Duration,
+    /// Peak resident memory, in bytes.
+    pub peak_memory: u64,
+}
+
+impl RunOutcome {
+    /// True if the process ran out of either its wall-clock or CPU budget.
+    pub fn exceeded_time(&self, limits: &Limits) -> bool {
+        self.killed_on_timeout || self.cpu_time >= limits.cpu_time
+    }
+
+    pub fn exceeded_memory(&self, limits: &Limits) -> bool {
+        self.peak_memory > limits.memory
+    }
+}
+
+/// Spawns `command`, feeds it `input` on stdin and waits for it to exit,
+/// killing it once the wall-clock or output limit has been exceeded.
+pub fn run(command: Command, input: &[u8], limits: &Limits) -> io::Result<RunOutcome> {
+    let input = input.to_vec();
+    let (outcome, ()) = run_with(command, limits, false, move |mut stdin, _| {
+        // A program is free to exit without reading all of its input.
+        let _ = stdin.write_all(&input);
+    })?;
+    Ok(outcome)
+}
+
+/// Like [`run`], but plays the scripted conversation `steps` with the program
+/// instead of writing its input up front.
+pub fn run_interactive(command: Command, steps: &[Step], limits: &Limits) -> io::Result<(RunOutcome, Transcript)> {
+    let steps = steps.to_vec();
+    run_with(command, limits, true, move |stdin, output| {
+        let output = output.expect("output is forwarded to interactive runs");
+        interaction::converse(stdin, output, &steps)
+    })
+}
+
+/// Spawns `command` and hands its stdin to `feed` on a thread of its own, along
+/// with each chunk of stdout as it is read if `forward_output` is set.
+fn run_with<T, F>(mut command: Command, limits: &Limits, forward_output: bool, feed: F) -> io::Result<(RunOutcome, T)>
+where
+    T: Send + 'static,
+    F: FnOnce(ChildStdin, Option<Receiver<Vec<u8>>>) -> T + Send + 'static,
+{
+    let start = Instant::now();
+    // A process group of its own lets the runner kill anything the program forked.
+    let mut child = command
+        .process_group(0)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Feed stdin and drain stdout on their own threads so that a program which
+    // neither reads its input nor stops writing cannot block the deadline check.
+    let (sender, receiver) = if forward_output {
+        let (sender, receiver) = mpsc::channel();
+        (Some(sender), Some(receiver))
+    } else {
+        (None, None)
+    };
+    let stdin = child.stdin.take().expect("stdin is piped");
+    let writer = thread::spawn(move || feed(stdin, receiver));
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let output_exceeded = Arc::new(AtomicBool::new(false));
+    let reader = {
+        let output_exceeded = Arc::clone(&output_exceeded);
+        let max = limits.output;
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            let mut chunk = [0; 4096];
+            loop {
+                let n = match stdout.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(n) => n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                };
+                if let Some(sender) = &sender {
+                    let _ = sender.send(chunk[..n].to_vec());
+                }
+                buf.extend_from_slice(&chunk[..n]);
+                if buf.len() > max {
+                    buf.truncate(max);
+                    output_exceeded.store(true, Ordering::SeqCst);
+                    break;
+                }
+            }
+            buf
+        })
+    };
+
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let stderr_reader = {
+        let max = limits.stderr;
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            let mut stderr = stderr;
+            let _ = (&mut stderr).take(max as u64).read_to_end(&mut buf);
+            // Keep draining so the program never blocks on a full stderr pipe.
+            let _ = io::copy(&mut stderr, &mut io::sink());
+            buf
+        })
+    };
+
+    let pid = child.id() as libc::pid_t;
+    let mut killed_on_timeout = false;
+    let mut killed_on_output = false;
+    let (status, usage) = loop {
+        if let Some(done) = wait4(pid, libc::WNOHANG)? {
+            break done;
+        }
+        let timed_out = start.elapsed() >= limits.wall_time;
+        let too_much_output = output_exceeded.load(Ordering::SeqCst);
+        if timed_out || too_much_output {
+            kill_group(pid);
+            killed_on_timeout = timed_out;
+            killed_on_output = too_much_output;
+            break wait4(pid, 0)?.expect("blocking wait4 returns a status");
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    let wall_time = start.elapsed();
+    // Stragglers would otherwise keep the output pipes open.
+    kill_group(pid);
+
+    let stdout = reader.join().unwrap_or_default();
+    let stderr = stderr_reader.join().unwrap_or_default();
+    let fed = writer.join().map_err(|_| io::Error::other("the stdin thread panicked"))?;
+
+    let outcome = RunOutcome {
+        stdout,
+        stderr,
+        sandbox_log: String::new(),
+        status,
+        killed_on_timeout,
+        killed_on_output: killed_on_output || output_exceeded.load(Ordering::SeqCst),
+        cpu_time: timeval_to_duration(usage.ru_utime) + timeval_to_duration(usage.ru_stime),
+        wall_time,
+        // ru_maxrss is reported in kilobytes on Linux.
+        peak_memory: usage.ru_maxrss as u64 * 1024,
+    };
+    Ok((outcome, fed))
+}
+
+/// Reaps `pid`, returning its status and the resources it (and any children it
+/// waited for) used, or `None` if it is still running under `WNOHANG`.
+fn wait4(pid: libc::pid_t, options: libc::c_int) -> io::Result<Option<(ExitStatus, libc::rusage)>> {
+    let mut status: libc::c_int = 0;
+    // SAFETY: an all-zero `rusage` is a valid value and is fully overwritten by wait4.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        // SAFETY: `status` and `usage` are valid, writable locals.
+        let ret = unsafe { libc::wait4(pid, &mut status, options, &mut usage) };
+        match ret {
+            0 => return Ok(None),
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            _ => return Ok(Some((ExitStatus::from_raw(status), usage))),
+        }
+    }
+}
+
+fn kill_group(pgid: libc::pid_t) {
+    // SAFETY: kill has no memory-safety preconditions; a missing group is ESRCH.
+    unsafe {
+        libc::kill(-pgid, libc::SIGKILL);
+    }
+}
+
+fn timeval_to_duration(tv: libc::timeval) -> Duration {
+    Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+}
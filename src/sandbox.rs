@@ -0,0 +1,349 @@
+// Sandbox backends that compile and run submissions in isolation.
+
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+
+use crate::interaction::{Step, Transcript};
+use crate::profile::{Profile, TomlProfile};
+use crate::runner::{self, Limits, RunOutcome, Termination};
+use crate::seccomp;
+
+/// Turns a job into a command that runs it in isolation under the job's limits.
+/// The runner spawns the command and enforces the wall-clock and output limits.
+pub trait Sandbox: Send + Sync {
+    /// Name shown in logs and error messages.
+    fn name(&self) -> &'static str;
+
+    /// Builds the command that runs `job` inside this sandbox.
+    fn command(&self, job: &Job) -> Command;
+
+    /// Runs `job` with `input` on stdin, collecting the sandbox's own log
+    /// separately from the program's stderr.
+    fn run(&self, job: &Job, input: &[u8]) -> io::Result<RunOutcome> {
+        let mut outcome = runner::run(self.command(job), input, job.limits)?;
+        collect_log(job, &mut outcome);
+        Ok(outcome)
+    }
+
+    /// Runs `job`, playing the scripted conversation `steps` over its stdin and stdout.
+    fn run_interactive(&self, job: &Job, steps: &[Step]) -> io::Result<(RunOutcome, Transcript)> {
+        let (mut outcome, transcript) = runner::run_interactive(self.command(job), steps, job.limits)?;
+        collect_log(job, &mut outcome);
+        Ok((outcome, transcript))
+    }
+
+    /// How the sandboxed program (not the sandbox itself) ended.
+    fn termination(&self, outcome: &RunOutcome) -> Termination {
+        Termination::from_status(outcome.status)
+    }
+
+    /// The system call a run was killed for by the job's seccomp policy, if any.
+    /// Backends that don't enforce seccomp policies never report one.
+    fn blocked_syscall(&self, _outcome: &RunOutcome) -> Option<String> {
+        None
+    }
+}
+
+/// Moves the sandbox's own log for `job` into `outcome`.
+fn collect_log(job: &Job, outcome: &mut RunOutcome) {
+    if let Some(path) = job.log_file {
+        outcome.sandbox_log = fs::read_to_string(path).unwrap_or_default();
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Sandbox backend, chosen with the `sandbox` key in Rocket's configuration.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Nsjail,
+    Bubblewrap,
+    /// Plain child processes with rlimits only. For development machines without
+    /// a jail; offers no isolation.
+    Local,
+}
+
+impl Backend {
+    pub fn build(self) -> Box<dyn Sandbox> {
+        match self {
+            Backend::Nsjail => Box::new(NsJail),
+            Backend::Bubblewrap => Box::new(Bubblewrap),
+            Backend::Local => Box::new(LocalProcess),
+        }
+    }
+}
+
+/// A host directory made visible inside the jail at the same path.
+pub struct Mount<'a> {
+    pub path: &'a Path,
+    pub writable: bool,
+}
+
+impl<'a> Mount<'a> {
+    pub fn read_only(path: &'a Path) -> Self {
+        Mount { path, writable: false }
+    }
+
+    pub fn writable(path: &'a Path) -> Self {
+        Mount { path, writable: true }
+    }
+}
+
+/// System directories holding the compiler, its helpers and the C headers.
+const COMPILER_DIRS: [&str; 4] = ["/bin", "/lib", "/lib64", "/usr"];
+
+fn mounts_for<'a>(system_dirs: &'static [&'static str], work_dir: &'a Path) -> Vec<Mount<'a>> {
+    let mut mounts: Vec<Mount> = system_dirs
+        .iter()
+        .map(|dir| Path::new(*dir))
+        .filter(|dir| dir.exists())
+        .map(Mount::read_only)
+        .collect();
+    mounts.push(Mount::writable(work_dir));
+    mounts
+}
+
+/// Mounts for running a compiled submission from `work_dir`. Submissions are
+/// linked statically and need no system directory, so none is mounted: the
+/// program finds no other executable to start.
+pub fn runtime_mounts(work_dir: &Path) -> Vec<Mount<'_>> {
+    mounts_for(&[], work_dir)
+}
+
+/// Mounts for compiling a submission in `work_dir`. Nothing outside the
+/// toolchain and the submission itself is visible, so `#include "/etc/shadow"`
+/// finds nothing.
+pub fn compiler_mounts(work_dir: &Path) -> Vec<Mount<'_>> {
+    mounts_for(&COMPILER_DIRS, work_dir)
+}
+
+/// What to run inside the jail and what it may see.
+pub struct Job<'a> {
+    pub program: &'a Path,
+    pub args: &'a [String],
+    pub mounts: &'a [Mount<'a>],
+    /// Working directory inside the jail.
+    pub cwd: Option<&'a Path>,
+    /// The only environment variables the program sees.
+    pub env: &'a [(&'a str, String)],
+    pub limits: &'a Limits,
+    /// Extra jail options from a sandbox profile file.
+    pub profile: Option<&'a Profile>,
+    /// Kafel seccomp policy the program runs under, if any.
+    pub seccomp_policy: Option<&'a str>,
+    /// Host file, outside every mount, for the sandbox's own diagnostics.
+    pub log_file: Option<&'a Path>,
+}
+
+/// Runs jobs under NSJail.
+pub struct NsJail;
+
+impl Sandbox for NsJail {
+    fn name(&self) -> &'static str {
+        "NSJail"
+    }
+
+    /// NSJail enforces the CPU limit (in whole seconds) and a wall-clock backstop
+    /// in case the runner itself fails to kill the process. The address space
+    /// limit leaves headroom over the resident memory limit the runner checks,
+    /// since the C runtime maps far more than it touches.
+    ///
+    /// An nsjail config profile comes first on the command line, so the job's
+    /// own limits and mounts are applied on top of it.
+    fn command(&self, job: &Job) -> Command {
+        let limits = job.limits;
+        let cpu_secs = limits.cpu_time.as_secs() + 1;
+        let wall_secs = limits.wall_time.as_secs() + 2;
+        let as_mb = address_space_limit(limits) / (1024 * 1024);
+        let fsize_mb = limits.file_size.div_ceil(1024 * 1024);
+
+        let mut jail = Command::new("nsjail");
+        if let Some(Profile::NsjailConfig(path)) = job.profile {
+            jail.arg("--config").arg(path);
+        }
+        jail.args(["--mode=exec", "--disable_clone_newuser"]);
+        if let Some(log) = job.log_file {
+            jail.arg("--log").arg(log);
+        }
+        jail
+            .arg("--rlimit_cpu").arg(cpu_secs.to_string())
+            .arg("--time_limit").arg(wall_secs.to_string())
+            .arg("--rlimit_as").arg(as_mb.to_string())
+            .arg("--rlimit_nproc").arg(limits.processes.to_string())
+            .arg("--rlimit_fsize").arg(fsize_mb.to_string());
+        for mount in job.mounts {
+            let flag = if mount.writable { "--bindmount" } else { "--bindmount_ro" };
+            jail.arg(flag).arg(mount.path);
+        }
+        // Without --keep_env the program starts with only these variables.
+        for (key, value) in job.env {
+            jail.arg("--env").arg(format!("{}={}", key, value));
+        }
+        if let Some(cwd) = job.cwd {
+            jail.arg("--cwd").arg(cwd);
+        }
+        if let Some(policy) = job.seccomp_policy {
+            jail.arg("--seccomp_string").arg(policy);
+        }
+        if let Some(Profile::Toml(profile)) = job.profile {
+            apply_nsjail_profile(&mut jail, profile);
+        }
+        jail.arg("--").arg(job.program).args(job.args);
+        jail
+    }
+
+    fn termination(&self, outcome: &RunOutcome) -> Termination {
+        Termination::from_shell_convention(outcome.status)
+    }
+
+    fn blocked_syscall(&self, outcome: &RunOutcome) -> Option<String> {
+        seccomp::parse_nsjail_violation(&outcome.sandbox_log)
+    }
+}
+
+/// Runs jobs under bubblewrap. bwrap has no rlimit options, so the limits are
+/// set on the bwrap process itself and inherited by the sandboxed program.
+/// Seccomp policies and the process limit are not applied.
+pub struct Bubblewrap;
+
+impl Sandbox for Bubblewrap {
+    fn name(&self) -> &'static str {
+        "bubblewrap"
+    }
+
+    fn command(&self, job: &Job) -> Command {
+        let mut bwrap = Command::new("bwrap");
+        bwrap.args(["--unshare-all", "--die-with-parent", "--new-session", "--clearenv"])
+            .args(["--proc", "/proc", "--dev", "/dev", "--tmpfs", "/tmp"]);
+        for mount in job.mounts {
+            let flag = if mount.writable { "--bind" } else { "--ro-bind" };
+            bwrap.arg(flag).arg(mount.path).arg(mount.path);
+        }
+        for (key, value) in job.env {
+            bwrap.arg("--setenv").arg(key).arg(value);
+        }
+        if let Some(cwd) = job.cwd {
+            bwrap.arg("--chdir").arg(cwd);
+        }
+        if let Some(Profile::Toml(profile)) = job.profile {
+            apply_bubblewrap_profile(&mut bwrap, profile);
+        }
+        bwrap.arg("--").arg(job.program).args(job.args);
+        set_rlimits(&mut bwrap, job.limits);
+        bwrap
+    }
+
+    fn termination(&self, outcome: &RunOutcome) -> Termination {
+        Termination::from_shell_convention(outcome.status)
+    }
+}
+
+/// Runs jobs as ordinary child processes with rlimits and a scrubbed
+/// environment. Mounts, profiles and seccomp policies are ignored: the program
+/// sees the whole filesystem and may make any system call.
+pub struct LocalProcess;
+
+impl Sandbox for LocalProcess {
+    fn name(&self) -> &'static str {
+        "local process"
+    }
+
+    fn command(&self, job: &Job) -> Command {
+        let mut command = Command::new(job.program);
+        command.args(job.args).env_clear();
+        for (key, value) in job.env {
+            command.env(key, value);
+        }
+        if let Some(cwd) = job.cwd {
+            command.current_dir(cwd);
+        }
+        set_rlimits(&mut command, job.limits);
+        command
+    }
+}
+
+fn apply_nsjail_profile(jail: &mut Command, profile: &TomlProfile) {
+    for path in &profile.read_only_mounts {
+        jail.arg("--bindmount_ro").arg(path);
+    }
+    for path in &profile.writable_mounts {
+        jail.arg("--bindmount").arg(path);
+    }
+    for path in &profile.tmpfs_mounts {
+        jail.arg("--tmpfsmount").arg(path);
+    }
+    let numeric = [
+        ("--user", profile.user.map(u64::from)),
+        ("--group", profile.group.map(u64::from)),
+        ("--cgroup_mem_max", profile.cgroup_mem_max),
+        ("--cgroup_pids_max", profile.cgroup_pids_max),
+        ("--cgroup_cpu_ms_per_sec", profile.cgroup_cpu_ms_per_sec),
+    ];
+    for (flag, value) in numeric {
+        if let Some(value) = value {
+            jail.arg(flag).arg(value.to_string());
+        }
+    }
+    if let Some(hostname) = &profile.hostname {
+        jail.arg("--hostname").arg(hostname);
+    }
+    jail.args(&profile.extra_args);
+}
+
+/// bubblewrap has no cgroup support, so those settings are ignored.
+fn apply_bubblewrap_profile(bwrap: &mut Command, profile: &TomlProfile) {
+    for path in &profile.read_only_mounts {
+        bwrap.arg("--ro-bind").arg(path).arg(path);
+    }
+    for path in &profile.writable_mounts {
+        bwrap.arg("--bind").arg(path).arg(path);
+    }
+    for path in &profile.tmpfs_mounts {
+        bwrap.arg("--tmpfs").arg(path);
+    }
+    if let Some(user) = profile.user {
+        bwrap.arg("--uid").arg(user.to_string());
+    }
+    if let Some(group) = profile.group {
+        bwrap.arg("--gid").arg(group.to_string());
+    }
+    if let Some(hostname) = &profile.hostname {
+        bwrap.arg("--hostname").arg(hostname);
+    }
+    bwrap.args(&profile.extra_args);
+}
+
+/// Address space rlimit for `limits`; see [`NsJail`] for why it exceeds the memory limit.
+fn address_space_limit(limits: &Limits) -> u64 {
+    limits.memory.saturating_mul(2)
+}
+
+/// Applies `limits` as rlimits in the child between fork and exec.
+///
+/// The process limit is left out: RLIMIT_NPROC counts every process the
+/// server's user owns, not just the job's, so on a busy grader it would make
+/// gcc fail to start its helpers and runs fail at random.
+fn set_rlimits(command: &mut Command, limits: &Limits) {
+    let rlimits = [
+        (libc::RLIMIT_CPU, limits.cpu_time.as_secs() + 1),
+        (libc::RLIMIT_AS, address_space_limit(limits)),
+        (libc::RLIMIT_FSIZE, limits.file_size),
+    ];
+    // SAFETY: the closure only calls setrlimit, which is async-signal-safe.
+    unsafe {
+        command.pre_exec(move || {
+            for &(resource, value) in &rlimits {
+                let limit = libc::rlimit { rlim_cur: value, rlim_max: value };
+                if libc::setrlimit(resource, &limit) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,70 @@
+// Turning per-test results into points and a grade.
+
+use serde::Serialize;
+
+use crate::grading::{TestResult, Verdict};
+use crate::questions::{Question, TestCase};
+
+/// Name under which tests without a group are totalled.
+const UNGROUPED: &str = "Other tests";
+
+/// Points earned in one group of tests.
+#[derive(Serialize, Debug, Clone)]
+pub struct GroupScore {
+    pub name: String,
+    pub earned: f64,
+    pub possible: f64,
+}
+
+/// A submission's points, per group and in total.
+#[derive(Serialize, Debug, Clone)]
+pub struct Score {
+    pub groups: Vec<GroupScore>,
+    /// The total mapped onto the question's `max_points`, or the raw points if it has none.
+    pub grade: f64,
+    pub max_grade: f64,
+}
+
+/// Fraction of a test's points a result earns: the checker's score if it gave
+/// one, otherwise all of them for a pass and none for anything else.
+pub fn credit(result: &TestResult) -> f64 {
+    match (&result.verdict, result.score) {
+        (Verdict::Passed | Verdict::Failed, Some(score)) => score,
+        (Verdict::Passed, None) => 1.0,
+        _ => 0.0,
+    }
+}
+
+/// Adds up the points `results` earned for `tests`, which they match one to one.
+/// Each group contributes at most its cap from the question's `group_caps`.
+pub fn score(question: &Question, tests: &[TestCase], results: &[TestResult]) -> Score {
+    // Groups are listed in the order their first test appears.
+    let mut groups: Vec<GroupScore> = Vec::new();
+    for (case, result) in tests.iter().zip(results) {
+        let name = case.group.as_deref().unwrap_or(UNGROUPED);
+        let index = match groups.iter().position(|group| group.name == name) {
+            Some(index) => index,
+            None => {
+                groups.push(GroupScore { name: name.to_string(), earned: 0.0, possible: 0.0 });
+                groups.len() - 1
+            }
+        };
+        let points = case.points();
+        groups[index].earned += points * credit(result);
+        groups[index].possible += points;
+    }
+    for group in &mut groups {
+        if let Some(&cap) = question.settings.group_caps.get(&group.name) {
+            group.earned = group.earned.min(cap);
+            group.possible = group.possible.min(cap);
+        }
+    }
+    let earned: f64 = groups.iter().map(|g| g.earned).sum();
+    let possible: f64 = groups.iter().map(|g| g.possible).sum();
+    let (grade, max_grade) = match question.settings.max_points {
+        Some(max) if possible > 0.0 => (earned / possible * max, max),
+        Some(max) => (0.0, max),
+        None => (earned, possible),
+    };
+    Score { groups, grade, max_grade }
+}
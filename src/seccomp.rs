@@ -0,0 +1,146 @@
+// Seccomp policies for student programs, written in the Kafel language nsjail understands.
+//
+// `execve` has to be allowed, because nsjail installs the policy before it
+// executes the student's program. It cannot be used to start anything else:
+// submissions are linked statically and run with no system directory mounted,
+// so the jail holds no executable but the submission itself. A program the
+// submission writes and then executes would still run under this same policy,
+// in the same jail and with the same limits.
+
+/// System calls a C program reading stdin and writing stdout needs, including
+/// those made by the static C library's startup code and by `abort()`.
+const DEFAULT_ALLOWED: &[&str] = &[
+    "read", "write", "readv", "writev", "pread64", "lseek", "close",
+    "fstat", "newfstatat", "stat", "lstat", "access", "faccessat", "readlink", "getcwd",
+    "brk", "mmap", "munmap", "mremap", "mprotect", "madvise",
+    "arch_prctl", "set_tid_address", "set_robust_list", "rseq", "prlimit64", "getrandom",
+    "rt_sigaction", "rt_sigprocmask", "rt_sigreturn", "tgkill", "getpid", "gettid",
+    "futex", "clock_gettime", "clock_nanosleep", "nanosleep", "gettimeofday", "time",
+    "uname", "ioctl", "fcntl", "dup", "dup2", "dup3", "sysinfo", "sched_yield",
+    "execve", "exit", "exit_group",
+];
+
+/// Opening files is allowed by default only without write access or O_CREAT
+/// (`flags & (O_WRONLY | O_RDWR | O_CREAT) == 0`), which is all reading input files needs.
+const READ_ONLY_OPEN: &str =
+    "open(path, flags, mode) { (flags & 0x43) == 0 }, openat(dirfd, path, flags, mode) { (flags & 0x43) == 0 }";
+
+/// Named groups of system calls a question can opt into.
+const GROUPS: &[(&str, &[&str])] = &[
+    (
+        "file_io",
+        &[
+            "open", "openat", "creat", "unlink", "unlinkat", "rename", "renameat",
+            "mkdir", "mkdirat", "rmdir", "ftruncate", "truncate", "fsync", "getdents64",
+        ],
+    ),
+    ("processes", &["fork", "vfork", "clone", "clone3", "wait4", "kill", "pipe", "pipe2"]),
+];
+
+/// Checks that every entry of a question's `allowed_syscalls` is a group name or
+/// looks like a system call name, so it can be pasted into a policy safely.
+pub fn validate(allowed: &[String]) -> Result<(), String> {
+    for name in allowed {
+        let valid = !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid {
+            return Err(format!("\"{}\" is not a system call or system call group name", name));
+        }
+    }
+    Ok(())
+}
+
+/// Builds a policy allowing the default system calls plus `extra`, which may
+/// name groups such as `file_io`. Anything else kills the program with SIGSYS.
+pub fn policy(extra: &[String]) -> String {
+    let mut allowed: Vec<&str> = DEFAULT_ALLOWED.to_vec();
+    for name in extra {
+        match GROUPS.iter().find(|(group, _)| group == name) {
+            Some((_, syscalls)) => allowed.extend_from_slice(syscalls),
+            None => allowed.push(name),
+        }
+    }
+    let unrestricted_open = allowed.iter().any(|&name| name == "open" || name == "openat");
+    allowed.retain(|&name| name != "open" && name != "openat");
+    allowed.sort_unstable();
+    allowed.dedup();
+
+    let mut rules = allowed.join(", ");
+    if unrestricted_open {
+        rules.push_str(", open, openat");
+    } else {
+        rules.push_str(", ");
+        rules.push_str(READ_ONLY_OPEN);
+    }
+    format!("ALLOW {{ {} }} DEFAULT KILL", rules)
+}
+
+/// Name of system call `number` on this architecture, for verdicts.
+pub fn syscall_name(number: u64) -> String {
+    match SYSCALL_NAMES.iter().find(|(n, _)| *n == number) {
+        Some((_, name)) => name.to_string(),
+        None => format!("#{}", number),
+    }
+}
+
+/// x86_64 numbers of system calls a student program is likely to be stopped for.
+#[cfg(target_arch = "x86_64")]
+const SYSCALL_NAMES: &[(u64, &str)] = &[
+    (2, "open"), (16, "ioctl"), (22, "pipe"), (32, "dup"), (33, "dup2"), (35, "nanosleep"),
+    (41, "socket"), (42, "connect"), (43, "accept"), (44, "sendto"), (45, "recvfrom"),
+    (46, "sendmsg"), (47, "recvmsg"), (48, "shutdown"), (49, "bind"), (50, "listen"),
+    (53, "socketpair"), (56, "clone"), (57, "fork"), (58, "vfork"), (59, "execve"),
+    (61, "wait4"), (62, "kill"), (74, "fsync"), (76, "truncate"), (77, "ftruncate"),
+    (78, "getdents"), (80, "chdir"), (82, "rename"), (83, "mkdir"), (84, "rmdir"),
+    (85, "creat"), (86, "link"), (87, "unlink"), (88, "symlink"), (90, "chmod"),
+    (92, "chown"), (101, "ptrace"), (105, "setuid"), (106, "setgid"), (112, "setsid"),
+    (157, "prctl"), (161, "chroot"), (165, "mount"), (166, "umount2"), (169, "reboot"),
+    (217, "getdents64"), (257, "openat"), (258, "mkdirat"), (263, "unlinkat"),
+    (264, "renameat"), (272, "unshare"), (293, "pipe2"), (310, "process_vm_readv"),
+    (311, "process_vm_writev"), (317, "seccomp"), (321, "bpf"), (322, "execveat"),
+    (425, "io_uring_setup"), (435, "clone3"),
+];
+
+#[cfg(not(target_arch = "x86_64"))]
+const SYSCALL_NAMES: &[(u64, &str)] = &[];
+
+/// Finds the system call nsjail reports when a program is killed by its seccomp
+/// policy, from log lines such as
+/// `pid=12 commited a syscall/seccomp violation and exited with SIGSYS` followed by
+/// `pid=12, Syscall number:41, Arguments: ...`.
+pub fn parse_nsjail_violation(log: &str) -> Option<String> {
+    if !log.contains("seccomp violation") {
+        return None;
+    }
+    let number = log.split("Syscall number:").nth(1).and_then(|rest| {
+        let digits: String = rest.trim_start().chars().take_while(|c| c.is_ascii_digit()).collect();
+        digits.parse().ok()
+    });
+    Some(match number {
+        Some(number) => syscall_name(number),
+        None => "unknown".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn parses_nsjail_violation_log() {
+        let log = "\
+[I][2024-05-02T10:11:12+0000][4021] executeChild():270 Executing '/work/a.out' for '[STANDALONE MODE]'
+[W][2024-05-02T10:11:12+0000][4020] seccompViolation():263 pid=4021 committed a syscall/seccomp violation and exited with SIGSYS
+[W][2024-05-02T10:11:12+0000][4020] seccompViolation():271 pid=4021, Syscall number: 41, Arguments: 0x2, 0x1, 0, 0, 0, 0, SP: 0x7ffd5c1e7a08, PC: 0x401b6d, si_syscall: 41, si_errno: 0
+[I][2024-05-02T10:11:12+0000][4020] reapProc():311 pid=4021 ([STANDALONE MODE]) exited with status: 159, (PIDs left: 0)
+";
+        assert_eq!(parse_nsjail_violation(log).as_deref(), Some("socket"));
+    }
+
+    #[test]
+    fn ignores_log_without_violation() {
+        let log = "[I][2024-05-02T10:11:12+0000][4020] reapProc():311 pid=4021 ([STANDALONE MODE]) exited with status: 0, (PIDs left: 0)\n";
+        assert_eq!(parse_nsjail_violation(log), None);
+    }
+}
@@ -0,0 +1,467 @@
+// Every submission and its results, kept in an SQLite database.
+//
+// A submission is one row of `submissions`, holding its source and what the
+// compiler printed, with a row of `results` per test it was run against and a
+// row of `group_scores` per group of tests. Enum values such as verdicts are
+// stored by their serde names, so the tables can be queried directly, as in
+// `SELECT COUNT(*) FROM results WHERE description = 'No input' AND verdict != 'passed'`.
+// Submissions made while logged in belong to a row of `students`, which also
+// holds each person's role, and staff can leave `feedback` on any submission.
+//
+// The schema is changed only by appending to `MIGRATIONS`; the database's
+// `user_version` counts the migrations already applied to it.
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::auth::Role;
+use crate::grading::{TestResult, Usage, Verdict};
+use crate::questions::TestCase;
+use crate::runner::Termination;
+use crate::scoring::{GroupScore, Score};
+
+const MIGRATIONS: &[&str] = &["
+CREATE TABLE IF NOT EXISTS submissions (
+    id TEXT PRIMARY KEY,
+    question TEXT NOT NULL,
+    submitted_at TEXT NOT NULL DEFAULT (datetime('now')),
+    source TEXT NOT NULL,
+    compiled INTEGER NOT NULL,
+    compiler_output TEXT NOT NULL,
+    seed INTEGER,
+    grade REAL,
+    max_grade REAL
+);
+CREATE TABLE IF NOT EXISTS results (
+    submission_id TEXT NOT NULL REFERENCES submissions(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    description TEXT NOT NULL,
+    test_group TEXT,
+    visibility TEXT NOT NULL,
+    points REAL NOT NULL,
+    verdict TEXT NOT NULL,
+    verdict_reason TEXT,
+    details TEXT NOT NULL,
+    stderr TEXT NOT NULL,
+    cpu_ms REAL,
+    wall_ms REAL,
+    memory_bytes INTEGER,
+    exit_code INTEGER,
+    signal INTEGER,
+    score REAL,
+    file_diffs TEXT NOT NULL,
+    transcript TEXT,
+    PRIMARY KEY (submission_id, position)
+);
+CREATE TABLE IF NOT EXISTS group_scores (
+    submission_id TEXT NOT NULL REFERENCES submissions(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    earned REAL NOT NULL,
+    possible REAL NOT NULL,
+    PRIMARY KEY (submission_id, position)
+);
+CREATE INDEX IF NOT EXISTS submissions_by_question ON submissions(question, submitted_at);
+", "
+CREATE TABLE students (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL
+);
+ALTER TABLE submissions ADD COLUMN student_id TEXT REFERENCES students(id);
+CREATE INDEX submissions_by_student ON submissions(student_id, submitted_at);
+", "
+ALTER TABLE students ADD COLUMN role TEXT NOT NULL DEFAULT 'student';
+CREATE TABLE feedback (
+    id INTEGER PRIMARY KEY,
+    submission_id TEXT NOT NULL REFERENCES submissions(id) ON DELETE CASCADE,
+    author TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+CREATE INDEX feedback_by_submission ON feedback(submission_id);
+"];
+
+/// A submission as it is recorded.
+pub struct Submission<'a> {
+    /// The submission's ID, which is also the name of its temporary directory.
+    pub id: &'a str,
+    /// The student who submitted it; absent for instructors' runs.
+    pub student: Option<&'a str>,
+    pub question: &'a str,
+    pub source: &'a str,
+    /// Whether the source compiled; if not, there are no tests or results.
+    pub compiled: bool,
+    /// Everything gcc printed, errors and warnings alike.
+    pub compiler_output: &'a str,
+    pub seed: Option<u64>,
+    /// The tests that were run, including generated ones, matching `results` one to one.
+    pub tests: &'a [TestCase],
+    pub results: &'a [TestResult],
+    pub score: Option<&'a Score>,
+}
+
+/// What a list of submissions shows about each one.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub id: String,
+    pub question: String,
+    /// UTC, as `YYYY-MM-DD HH:MM:SS`.
+    pub submitted_at: String,
+    pub compiled: bool,
+    /// Absent if the submission did not compile.
+    pub grade: Option<f64>,
+    pub max_grade: Option<f64>,
+    /// ID of the student who made it, if anyone was logged in.
+    pub student: Option<String>,
+}
+
+/// A person on the roster.
+#[derive(Debug, Clone)]
+pub struct StudentRecord {
+    pub id: String,
+    pub name: String,
+    /// Argon2 hash of the student's login token.
+    pub token_hash: String,
+    pub role: Role,
+}
+
+/// A comment a TA or instructor left on a submission.
+#[derive(Debug, Clone)]
+pub struct Feedback {
+    pub author: String,
+    pub body: String,
+    /// UTC, as `YYYY-MM-DD HH:MM:SS`.
+    pub created_at: String,
+}
+
+/// A recorded submission, read back from the database.
+#[derive(Debug, Clone)]
+pub struct StoredSubmission {
+    pub summary: Summary,
+    pub compiler_output: String,
+    pub seed: Option<u64>,
+    /// The tests as far as they were recorded: description, group, visibility and points.
+    pub tests: Vec<TestCase>,
+    pub results: Vec<TestResult>,
+    pub groups: Vec<GroupScore>,
+}
+
+const SUMMARY_COLUMNS: &str = "id, question, submitted_at, compiled, grade, max_grade, student_id";
+
+/// Most submissions listed at once for staff.
+const LIST_LIMIT: usize = 500;
+
+/// The submissions database, shared between requests.
+pub struct Store {
+    connection: Mutex<Connection>,
+}
+
+impl Store {
+    /// Opens the database at `path`, creating it or bringing its tables up to
+    /// date if needed.
+    pub fn open(path: &Path) -> rusqlite::Result<Store> {
+        let mut connection = Connection::open(path)?;
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let transaction = connection.transaction()?;
+            transaction.execute_batch(migration)?;
+            transaction.execute_batch(&format!("PRAGMA user_version = {}", i + 1))?;
+            transaction.commit()?;
+        }
+        Ok(Store { connection: Mutex::new(connection) })
+    }
+
+    /// Records `submission` and its results in one transaction.
+    pub fn record(&self, submission: &Submission) -> rusqlite::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO submissions (id, question, source, compiled, compiler_output, seed, grade, max_grade, student_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                submission.id,
+                submission.question,
+                submission.source,
+                submission.compiled,
+                submission.compiler_output,
+                // SQLite integers are signed; the cast keeps every bit of the seed.
+                submission.seed.map(|seed| seed as i64),
+                submission.score.map(|score| score.grade),
+                submission.score.map(|score| score.max_grade),
+                submission.student,
+            ],
+        )?;
+        for (position, (case, result)) in submission.tests.iter().zip(submission.results).enumerate() {
+            let (verdict, verdict_reason) = verdict_columns(result);
+            let (exit_code, signal) = match result.termination {
+                Some(Termination::Exited(code)) => (Some(code), None),
+                Some(Termination::Signaled(signal)) => (None, Some(signal)),
+                None => (None, None),
+            };
+            transaction.execute(
+                "INSERT INTO results (submission_id, position, description, test_group, visibility, points,
+                     verdict, verdict_reason, details, stderr, cpu_ms, wall_ms, memory_bytes, exit_code, signal,
+                     score, file_diffs, transcript)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+                params![
+                    submission.id,
+                    position as i64,
+                    result.description,
+                    case.group,
+                    serde_name(&case.visibility),
+                    case.points(),
+                    verdict,
+                    verdict_reason,
+                    result.details,
+                    result.stderr,
+                    result.usage.map(|usage| usage.cpu_time.as_secs_f64() * 1000.0),
+                    result.usage.map(|usage| usage.wall_time.as_secs_f64() * 1000.0),
+                    result.usage.map(|usage| usage.memory as i64),
+                    exit_code,
+                    signal,
+                    result.score,
+                    serde_json::to_string(&result.file_diffs).unwrap_or_default(),
+                    result.transcript.as_ref().and_then(|transcript| serde_json::to_string(transcript).ok()),
+                ],
+            )?;
+        }
+        if let Some(score) = submission.score {
+            for (position, group) in score.groups.iter().enumerate() {
+                transaction.execute(
+                    "INSERT INTO group_scores (submission_id, position, name, earned, possible)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![submission.id, position as i64, group.name, group.earned, group.possible],
+                )?;
+            }
+        }
+        transaction.commit()
+    }
+
+    /// Summaries of the submissions `student` made, newest first.
+    pub fn submissions_of(&self, student: &str) -> rusqlite::Result<Vec<Summary>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(&format!(
+            "SELECT {} FROM submissions WHERE student_id = ?1 ORDER BY submitted_at DESC, rowid DESC",
+            SUMMARY_COLUMNS
+        ))?;
+        let summaries = statement.query_map([student], summary_from_row)?.collect();
+        summaries
+    }
+
+    /// Summaries of the most recent submissions, newest first, optionally only
+    /// those for `question` or by `student`.
+    pub fn all_submissions(&self, question: Option<&str>, student: Option<&str>) -> rusqlite::Result<Vec<Summary>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(&format!(
+            "SELECT {} FROM submissions
+             WHERE (?1 IS NULL OR question = ?1) AND (?2 IS NULL OR student_id = ?2)
+             ORDER BY submitted_at DESC, rowid DESC LIMIT {}",
+            SUMMARY_COLUMNS, LIST_LIMIT
+        ))?;
+        let summaries = statement.query_map(params![question, student], summary_from_row)?.collect();
+        summaries
+    }
+
+    /// The student with ID `id`, if they are on the roster.
+    pub fn student(&self, id: &str) -> rusqlite::Result<Option<StudentRecord>> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .query_row("SELECT id, name, token_hash, role FROM students WHERE id = ?1", [id], student_from_row)
+            .optional()
+    }
+
+    /// Every student on the roster, by ID.
+    pub fn students(&self) -> rusqlite::Result<Vec<StudentRecord>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare("SELECT id, name, token_hash, role FROM students ORDER BY id")?;
+        let students = statement.query_map([], student_from_row)?.collect();
+        students
+    }
+
+    /// Adds a student, or renames them and replaces their token hash if
+    /// `token_hash` is given.
+    pub fn save_student(&self, id: &str, name: &str, token_hash: Option<&str>) -> rusqlite::Result<()> {
+        let connection = self.connection.lock().unwrap();
+        save_student(&connection, id, name, token_hash)
+    }
+
+    /// Saves each of `students`, given as ID, name and token hash, like
+    /// [`Store::save_student`], in one transaction: if any of them fails,
+    /// none is saved.
+    pub fn save_students(&self, students: &[(&str, &str, Option<&str>)]) -> rusqlite::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        for &(id, name, token_hash) in students {
+            save_student(&transaction, id, name, token_hash)?;
+        }
+        transaction.commit()
+    }
+
+    /// Gives the person with ID `id` a new role. Returns whether they are on the roster.
+    pub fn set_role(&self, id: &str, role: Role) -> rusqlite::Result<bool> {
+        let connection = self.connection.lock().unwrap();
+        let changed = connection.execute("UPDATE students SET role = ?2 WHERE id = ?1", params![id, serde_name(&role)])?;
+        Ok(changed > 0)
+    }
+
+    pub fn add_feedback(&self, submission_id: &str, author: &str, body: &str) -> rusqlite::Result<()> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT INTO feedback (submission_id, author, body) VALUES (?1, ?2, ?3)",
+            params![submission_id, author, body],
+        )?;
+        Ok(())
+    }
+
+    /// The feedback left on a submission, oldest first.
+    pub fn feedback(&self, submission_id: &str) -> rusqlite::Result<Vec<Feedback>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT author, body, created_at FROM feedback WHERE submission_id = ?1 ORDER BY id")?;
+        let feedback = statement
+            .query_map([submission_id], |row| Ok(Feedback { author: row.get(0)?, body: row.get(1)?, created_at: row.get(2)? }))?
+            .collect();
+        feedback
+    }
+
+    /// The submission with ID `id` and all of its results, if it was recorded.
+    pub fn load(&self, id: &str) -> rusqlite::Result<Option<StoredSubmission>> {
+        let connection = self.connection.lock().unwrap();
+        let found = connection
+            .query_row(
+                &format!("SELECT {}, compiler_output, seed FROM submissions WHERE id = ?1", SUMMARY_COLUMNS),
+                [id],
+                |row| {
+                    let seed: Option<i64> = row.get(8)?;
+                    Ok((summary_from_row(row)?, row.get::<_, String>(7)?, seed.map(|seed| seed as u64)))
+                },
+            )
+            .optional()?;
+        let Some((summary, compiler_output, seed)) = found else {
+            return Ok(None);
+        };
+
+        let mut statement = connection.prepare(
+            "SELECT description, test_group, visibility, points, verdict, verdict_reason, details, stderr,
+                 cpu_ms, wall_ms, memory_bytes, exit_code, signal, score, file_diffs, transcript
+             FROM results WHERE submission_id = ?1 ORDER BY position",
+        )?;
+        let rows = statement.query_map([id], |row| {
+            let description: String = row.get(0)?;
+            let case = TestCase {
+                description: description.clone(),
+                group: row.get(1)?,
+                visibility: from_serde_name(&row.get::<_, String>(2)?).unwrap_or_default(),
+                points: Some(row.get(3)?),
+                ..TestCase::default()
+            };
+            let usage = match (row.get::<_, Option<f64>>(8)?, row.get::<_, Option<f64>>(9)?, row.get::<_, Option<i64>>(10)?) {
+                (Some(cpu_ms), Some(wall_ms), Some(memory)) => Some(Usage {
+                    cpu_time: Duration::from_secs_f64(cpu_ms / 1000.0),
+                    wall_time: Duration::from_secs_f64(wall_ms / 1000.0),
+                    memory: memory as u64,
+                }),
+                _ => None,
+            };
+            let termination = match (row.get(11)?, row.get(12)?) {
+                (Some(code), _) => Some(Termination::Exited(code)),
+                (None, Some(signal)) => Some(Termination::Signaled(signal)),
+                (None, None) => None,
+            };
+            let result = TestResult {
+                description,
+                verdict: verdict_from_columns(row.get(4)?, row.get(5)?),
+                details: row.get(6)?,
+                stderr: row.get(7)?,
+                usage,
+                termination,
+                score: row.get(13)?,
+                file_diffs: serde_json::from_str(&row.get::<_, String>(14)?).unwrap_or_default(),
+                transcript: row.get::<_, Option<String>>(15)?.and_then(|json| serde_json::from_str(&json).ok()),
+            };
+            Ok((case, result))
+        })?;
+        let (tests, results) = rows.collect::<rusqlite::Result<Vec<_>>>()?.into_iter().unzip();
+
+        let mut statement = connection.prepare(
+            "SELECT name, earned, possible FROM group_scores WHERE submission_id = ?1 ORDER BY position",
+        )?;
+        let groups = statement
+            .query_map([id], |row| Ok(GroupScore { name: row.get(0)?, earned: row.get(1)?, possible: row.get(2)? }))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(Some(StoredSubmission { summary, compiler_output, seed, tests, results, groups }))
+    }
+}
+
+/// Reads the [`SUMMARY_COLUMNS`] at the start of `row`.
+fn summary_from_row(row: &Row) -> rusqlite::Result<Summary> {
+    Ok(Summary {
+        id: row.get(0)?,
+        question: row.get(1)?,
+        submitted_at: row.get(2)?,
+        compiled: row.get(3)?,
+        grade: row.get(4)?,
+        max_grade: row.get(5)?,
+        student: row.get(6)?,
+    })
+}
+
+fn save_student(connection: &Connection, id: &str, name: &str, token_hash: Option<&str>) -> rusqlite::Result<()> {
+    match token_hash {
+        Some(token_hash) => connection.execute(
+            "INSERT INTO students (id, name, token_hash) VALUES (?1, ?2, ?3)
+             ON CONFLICT (id) DO UPDATE SET name = excluded.name, token_hash = excluded.token_hash",
+            params![id, name, token_hash],
+        ),
+        None => connection.execute("UPDATE students SET name = ?2 WHERE id = ?1", params![id, name]),
+    }?;
+    Ok(())
+}
+
+fn student_from_row(row: &Row) -> rusqlite::Result<StudentRecord> {
+    Ok(StudentRecord {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        token_hash: row.get(2)?,
+        role: from_serde_name(&row.get::<_, String>(3)?).unwrap_or_default(),
+    })
+}
+
+/// The kind of a result's verdict and its reason, if the kind has one.
+fn verdict_columns(result: &TestResult) -> (String, Option<String>) {
+    let value = serde_json::to_value(&result.verdict).unwrap_or_default();
+    let kind = value["kind"].as_str().unwrap_or_default().to_string();
+    let reason = value.get("reason").and_then(|reason| reason.as_str()).map(str::to_string);
+    (kind, reason)
+}
+
+/// The verdict stored as `kind` and `reason`. A kind this version does not know
+/// is read as a runtime error, whose reason is the stored reason or, if there
+/// is none, the kind, since `Failed` has no room for either.
+fn verdict_from_columns(kind: String, reason: Option<String>) -> Verdict {
+    let mut value = serde_json::json!({ "kind": kind });
+    if let Some(reason) = &reason {
+        value["reason"] = serde_json::Value::from(reason.as_str());
+    }
+    serde_json::from_value(value).unwrap_or(Verdict::RuntimeError(reason.unwrap_or(kind)))
+}
+
+/// The name serde gives a unit enum variant, such as `name_only`.
+fn serde_name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// The unit enum variant serde calls `name`.
+fn from_serde_name<T: DeserializeOwned>(name: &str) -> Option<T> {
+    serde_json::from_value(serde_json::Value::from(name)).ok()
+}